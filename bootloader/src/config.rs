@@ -0,0 +1,98 @@
+use alloc::string::String;
+use common::boot::{CMDLINE_CAPACITY, CmdLine};
+use log::{debug, info, warn};
+use uefi::{CStr16, cstr16};
+
+use crate::read_file;
+
+const CONFIG_FILE_NAME: &CStr16 = cstr16!("maizono.cfg");
+
+/// Boot options read from `maizono.cfg` in the root directory of the boot volume.
+///
+/// Each line is `key=value`. Empty lines and lines starting with `#` are ignored.
+/// The valid lines are passed to the kernel as its command line,
+/// and the bootloader itself uses `video=<width>x<height>`.
+pub struct Config {
+    cmdline: String,
+}
+
+impl Config {
+    /// The config used when there is no config file.
+    fn default() -> Self {
+        Self {
+            cmdline: String::new(),
+        }
+    }
+
+    fn parse(src: &str) -> Self {
+        let mut cmdline = String::new();
+        for (i, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let is_valid = line
+                .split_once('=')
+                .is_some_and(|(key, _)| !key.trim().is_empty());
+            if !is_valid {
+                warn!("{} line {}: \"{}\" is not key=value. Skipped.", CONFIG_FILE_NAME, i + 1, line);
+                continue;
+            }
+
+            // +1 for the new line
+            if cmdline.len() + line.len() + 1 > CMDLINE_CAPACITY {
+                warn!("{} line {}: the command line is full. Skipped.", CONFIG_FILE_NAME, i + 1);
+                continue;
+            }
+            cmdline.push_str(line);
+            cmdline.push('\n');
+        }
+
+        Self { cmdline }
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.cmdline.lines().rev().find_map(|line| {
+            let (k, v) = line.split_once('=')?;
+            (k.trim() == key).then(|| v.trim())
+        })
+    }
+
+    /// `video=<width>x<height>`
+    pub fn video_mode(&self) -> Option<(usize, usize)> {
+        let value = self.get("video")?;
+        let resolution = value.split_once('x').and_then(|(width, height)| {
+            Some((width.parse().ok()?, height.parse().ok()?))
+        });
+        if resolution.is_none() {
+            warn!("video={} is not <width>x<height>. Ignored.", value);
+        }
+        resolution
+    }
+
+    pub fn cmdline(&self) -> CmdLine {
+        // parse keeps the command line within CMDLINE_CAPACITY
+        CmdLine::new(&self.cmdline).unwrap_or(CmdLine::empty())
+    }
+}
+
+/// Reads the config file. Falls back to the defaults if the file can't be read.
+pub fn load_config() -> Config {
+    let src = match read_file(CONFIG_FILE_NAME) {
+        Ok(src) => src,
+        Err(err) => {
+            info!("{} was not loaded. The defaults are used.", CONFIG_FILE_NAME);
+            debug!("{:#?}", err);
+            return Config::default();
+        }
+    };
+
+    match core::str::from_utf8(&src) {
+        Ok(src) => Config::parse(src),
+        Err(_) => {
+            warn!("{} is not valid UTF-8. The defaults are used.", CONFIG_FILE_NAME);
+            Config::default()
+        }
+    }
+}
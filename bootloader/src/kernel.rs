@@ -1,6 +1,5 @@
 use core::slice;
 
-use alloc::vec;
 use anyhow::{anyhow, bail, Error, Result};
 use common::boot::Kernel;
 use goblin::elf;
@@ -8,47 +7,16 @@ use log::debug;
 use uefi::{
     boot::{self, AllocateType, MemoryType},
     cstr16,
-    proto::media::file::{File, FileAttribute, FileInfo, FileMode, FileType},
     CStr16,
 };
 
-use crate::{file_info_size, open_root_dir};
+use crate::read_file;
 
 const KERNEL_FILE_NAME: &CStr16 = cstr16!("kernel.elf");
 const UEFI_PAGE_SIZE: usize = 0x1000;
 
 pub fn load_kernel() -> Result<Kernel> {
-    let mut root_dir = open_root_dir(boot::image_handle());
-
-    let mut kernel_file = match root_dir
-        .open(KERNEL_FILE_NAME, FileMode::Read, FileAttribute::empty())
-        .map_err(|e| Error::msg(e).context("Failed to open kernel file."))?
-        .into_type()
-        .map_err(|e| {
-            Error::msg(e).context(
-                "Failed to make the kernel file handler into file type (regular file or directory).",
-            )
-        })? {
-        FileType::Regular(f) => f,
-        FileType::Dir(_) => {
-            bail!(anyhow!(
-                "{} was a directory. It must be a regular file.",
-                KERNEL_FILE_NAME
-            ));
-        }
-    };
-
-    let mut kernel_file_info_vec = vec![0; file_info_size(KERNEL_FILE_NAME)];
-    let kernel_file_info = kernel_file
-        .get_info::<FileInfo>(&mut kernel_file_info_vec)
-        .map_err(|e| Error::msg(e).context("Failed to get information of kernel file."))?;
-
-    let kernel_file_size = kernel_file_info.file_size() as usize;
-
-    let mut kernel_file_vec = vec![0; kernel_file_size];
-    kernel_file
-        .read(&mut kernel_file_vec)
-        .map_err(|e| Error::msg(e).context("Failed to read data from the kernel file"))?;
+    let kernel_file_vec = read_file(KERNEL_FILE_NAME)?;
 
     Ok(load_elf(&kernel_file_vec)
         .map_err(|e| Error::msg(e).context("Failed to load the elf file"))?)
@@ -57,6 +25,7 @@ pub fn load_kernel() -> Result<Kernel> {
 fn load_elf(src: &[u8]) -> Result<Kernel> {
     let elf =
         elf::Elf::parse(src).map_err(|e| Error::msg(e).context("Failed to parse the elf."))?;
+    validate_elf(src, &elf).map_err(|e| e.context("The kernel elf is invalid."))?;
 
     let (dest_range, base_addr) = {
         let mut dest_start = 0;
@@ -82,7 +51,7 @@ fn load_elf(src: &[u8]) -> Result<Kernel> {
     .map_err(|e| Error::msg(e).context("Failed to allocate pages for the kernel."))?
     .as_ptr() as u64;
 
-    copy_load_segment(src, &elf);
+    copy_load_segment(src, &elf)?;
     debug!("base addr: 0x{:X}", base_addr);
     // copy_dynamic_segment(src, base_addr, &elf);
 
@@ -91,6 +60,75 @@ fn load_elf(src: &[u8]) -> Result<Kernel> {
     Ok(Kernel::new(base_addr, entry_point_addr))
 }
 
+/// Checks the elf before anything is written to memory so that a broken kernel file is reported
+/// as an error instead of corrupting memory or jumping to a wrong address.
+fn validate_elf(src: &[u8], elf: &elf::Elf) -> Result<()> {
+    if elf.header.e_type != elf::header::ET_EXEC {
+        bail!(
+            "The elf type must be ET_EXEC but it was {}.",
+            elf::header::et_to_str(elf.header.e_type)
+        );
+    }
+
+    let mut is_entry_in_segment = false;
+    for program_header in elf.program_headers.iter() {
+        if program_header.p_type != elf::program_header::PT_LOAD {
+            continue;
+        }
+
+        let segment_start = program_header.p_vaddr;
+        let segment_end = segment_start
+            .checked_add(program_header.p_memsz)
+            .ok_or_else(|| {
+                anyhow!(
+                    "The segment at 0x{:X} overflows the address space (size: 0x{:X}).",
+                    segment_start,
+                    program_header.p_memsz
+                )
+            })?;
+
+        if program_header.p_filesz > program_header.p_memsz {
+            bail!(
+                "The file size (0x{:X}) of the segment at 0x{:X} is larger than its memory size (0x{:X}).",
+                program_header.p_filesz,
+                segment_start,
+                program_header.p_memsz
+            );
+        }
+
+        let is_in_file = program_header
+            .p_offset
+            .checked_add(program_header.p_filesz)
+            .is_some_and(|file_end| file_end <= src.len() as u64);
+        if !is_in_file {
+            bail!(
+                "The segment at 0x{:X} refers to data outside of the file.",
+                segment_start
+            );
+        }
+
+        if program_header.is_write() && program_header.is_executable() {
+            bail!(
+                "The segment at 0x{:X} is both writable and executable.",
+                segment_start
+            );
+        }
+
+        if program_header.is_executable() && (segment_start..segment_end).contains(&elf.entry) {
+            is_entry_in_segment = true;
+        }
+    }
+
+    if !is_entry_in_segment {
+        bail!(
+            "The entry point 0x{:X} is not in any executable load segment.",
+            elf.entry
+        );
+    }
+
+    Ok(())
+}
+
 fn copy_load_segment(src: &[u8], elf: &elf::Elf) -> Result<()> {
     for program_header in elf.program_headers.iter() {
         if program_header.p_type != elf::program_header::PT_LOAD {
@@ -1,12 +1,15 @@
 #![no_main]
 #![no_std]
 
+mod config;
 mod kernel;
 
 use core::arch::asm;
 
 extern crate alloc;
 use alloc::format;
+use alloc::vec;
+use alloc::vec::Vec;
 use anyhow::Error;
 use anyhow::Result;
 use anyhow::anyhow;
@@ -15,20 +18,22 @@ use boot::MemoryType;
 use common::address::PhysPtr;
 use common::boot::BootInfo;
 use common::graphic::GraphicInfo;
+use common::memory_map::MemoryMap;
 use kernel::load_kernel;
 use log::debug;
 use log::error;
 use log::info;
+use log::warn;
 use runtime::Time;
 use uefi::boot::ScopedProtocol;
 use uefi::helpers;
-use uefi::mem::memory_map::MemoryMap;
-use uefi::proto::console::gop::GraphicsOutput;
+use uefi::mem::memory_map::MemoryMap as _;
+use uefi::proto::console::gop::{GraphicsOutput, Mode, PixelFormat};
 use uefi::table::cfg::ACPI2_GUID;
 use uefi::{
     CStr16,
     prelude::*,
-    proto::media::file::{Directory, File, FileAttribute, FileMode, FileType},
+    proto::media::file::{Directory, File, FileAttribute, FileInfo, FileMode, FileType},
 };
 
 #[entry]
@@ -39,6 +44,9 @@ fn efi_main() -> Status {
 fn main_inner() -> Status {
     helpers::init().unwrap();
 
+    info!("loading boot config");
+    let config = config::load_config();
+
     info!("opening gop");
     let mut gop = match open_gop() {
         Ok(g) => g,
@@ -48,6 +56,10 @@ fn main_inner() -> Status {
             panic!("panicked.");
         }
     };
+    if let Err(err) = select_gop_mode(&mut gop, config.video_mode()) {
+        // the mode the firmware set is still usable
+        print_error(&err.context("Failed to switch the video mode. The current mode is used."));
+    }
     let graphic_info =
         match GraphicInfo::from_gop(&mut gop) {
             Ok(info) => info,
@@ -77,9 +89,10 @@ fn main_inner() -> Status {
     info!("rsdp_addr: {:?}", rsdp_addr);
 
     info!("exiting boot services.");
-    let memory_map = unsafe { boot::exit_boot_services(boot::MemoryType::BOOT_SERVICES_DATA) };
+    let uefi_memory_map = unsafe { boot::exit_boot_services(boot::MemoryType::BOOT_SERVICES_DATA) };
+    let memory_map = MemoryMap::from_uefi(&uefi_memory_map);
 
-    let boot_info = BootInfo::new(graphic_info, memory_map, rsdp_addr);
+    let boot_info = BootInfo::new(graphic_info, memory_map, rsdp_addr, config.cmdline());
     kernel.run(&boot_info);
 
     loop {
@@ -101,6 +114,41 @@ fn open_root_dir(image: Handle) -> Directory {
     simple_file_system.open_volume().unwrap()
 }
 
+/// Reads the whole file in the root directory of the boot volume.
+fn read_file(file_name: &CStr16) -> Result<Vec<u8>> {
+    let mut root_dir = open_root_dir(boot::image_handle());
+
+    let mut file = match root_dir
+        .open(file_name, FileMode::Read, FileAttribute::empty())
+        .map_err(|e| Error::msg(e).context(format!("Failed to open {}.", file_name)))?
+        .into_type()
+        .map_err(|e| {
+            Error::msg(e).context(format!(
+                "Failed to make the handler of {} into file type (regular file or directory).",
+                file_name
+            ))
+        })? {
+        FileType::Regular(f) => f,
+        FileType::Dir(_) => {
+            bail!(anyhow!(
+                "{} was a directory. It must be a regular file.",
+                file_name
+            ));
+        }
+    };
+
+    let mut file_info_vec = vec![0; file_info_size(file_name)];
+    let file_info = file
+        .get_info::<FileInfo>(&mut file_info_vec)
+        .map_err(|e| Error::msg(e).context(format!("Failed to get information of {}.", file_name)))?;
+
+    let mut file_vec = vec![0; file_info.file_size() as usize];
+    file.read(&mut file_vec)
+        .map_err(|e| Error::msg(e).context(format!("Failed to read data from {}.", file_name)))?;
+
+    Ok(file_vec)
+}
+
 fn file_info_size(file_name: &CStr16) -> usize {
     // uefi::proto::media::file::FileInfo (version: 0.32.0) has following fields:
     // pub struct FileInfo {
@@ -147,6 +195,44 @@ fn open_gop() -> Result<ScopedProtocol<GraphicsOutput>> {
     Ok(gop)
 }
 
+/// Switches to the mode of `requested` resolution, or the highest resolution mode if it's not given or not available.
+/// Only modes which have 32-bit RGB or BGR pixels are used.
+fn select_gop_mode(gop: &mut GraphicsOutput, requested: Option<(usize, usize)>) -> Result<()> {
+    let is_usable =
+        |mode: &Mode| matches!(mode.info().pixel_format(), PixelFormat::Rgb | PixelFormat::Bgr);
+
+    let requested_mode = requested.and_then(|resolution| {
+        let mode = gop
+            .modes()
+            .find(|mode| is_usable(mode) && mode.info().resolution() == resolution);
+        if mode.is_none() {
+            warn!(
+                "video mode {}x{} is not available. The highest resolution is used.",
+                resolution.0, resolution.1
+            );
+        }
+        mode
+    });
+
+    let best_mode = match requested_mode {
+        Some(mode) => mode,
+        None => gop
+            .modes()
+            .filter(is_usable)
+            .max_by_key(|mode| {
+                let (width, height) = mode.info().resolution();
+                width * height
+            })
+            .ok_or(anyhow!("No video mode with RGB or BGR pixels is available."))?,
+    };
+
+    let (width, height) = best_mode.info().resolution();
+    info!("selected video mode: {}x{}", width, height);
+    gop.set_mode(&best_mode)
+        .map_err(|e| Error::msg(e).context(format!("Failed to set video mode {}x{}.", width, height)))?;
+    Ok(())
+}
+
 fn print_error(err: &Error) {
     error!("{:#?}", err);
 }
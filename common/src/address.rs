@@ -8,6 +8,10 @@ impl PhysPtr {
         Self { ptr: 0 }
     }
 
+    pub const fn new(ptr: u64) -> Self {
+        Self { ptr }
+    }
+
     pub fn from_ref<T>(ref_: &T) -> Self {
         Self {
             ptr: ref_ as *const T as u64,
@@ -42,3 +46,61 @@ impl PhysPtr {
         self.ptr = ptr;
     }
 }
+
+/// A virtual address. The kernel maps physical memory below `PHYS_WINDOW_SIZE` at a fixed offset
+/// (the physical memory window), so `phys_to_virt` is an addition.
+/// The other way around needs a page table walk.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct VirtPtr {
+    ptr: u64,
+}
+
+impl VirtPtr {
+    pub const fn null() -> Self {
+        Self { ptr: 0 }
+    }
+
+    pub const fn new(ptr: u64) -> Self {
+        Self { ptr }
+    }
+
+    pub fn from_ref<T>(ref_: &T) -> Self {
+        Self {
+            ptr: ref_ as *const T as u64,
+        }
+    }
+
+    pub fn from_ptr<T>(ptr: *const T) -> Self {
+        Self { ptr: ptr as u64 }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.ptr == 0
+    }
+
+    pub fn ptr<T>(&self) -> *const T {
+        self.ptr as *const T
+    }
+
+    pub fn mut_ptr<T>(&self) -> *mut T {
+        self.ptr as *mut T
+    }
+
+    pub const fn get(&self) -> u64 {
+        self.ptr
+    }
+}
+
+/// Start of the window where the kernel maps all physical memory below `PHYS_WINDOW_SIZE` at a fixed offset.
+/// Unlike the identity mapping, this stays when page tables for user processes are introduced.
+pub const PHYS_WINDOW_START: u64 = 0xffff_8880_0000_0000;
+/// The size of the physical memory window (64 GiB)
+pub const PHYS_WINDOW_SIZE: u64 = 64 * 1024 * 1024 * 1024;
+
+/// Converts a physical address to the virtual address at which the kernel accesses it
+/// in the physical memory window. `phys` must be below `PHYS_WINDOW_SIZE`.
+pub const fn phys_to_virt(phys: PhysPtr) -> VirtPtr {
+    VirtPtr {
+        ptr: PHYS_WINDOW_START + phys.ptr,
+    }
+}
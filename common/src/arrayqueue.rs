@@ -0,0 +1,225 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A lock-free single-producer single-consumer queue with a fixed capacity.
+///
+/// Meant for passing data from an interrupt handler (the producer) to a task (the consumer)
+/// without taking a lock in the interrupt handler.
+/// Only one context may call `push` and only one context may call `pop` at the same time.
+///
+/// `head` and `tail` count pops and pushes modulo `2 * CAP`, so `tail - head` is the length
+/// and a full queue is distinguished from an empty one. Wrapping at a multiple of `CAP`
+/// keeps `counter % CAP` continuous for any capacity.
+pub struct ArrayQueue<T, const CAP: usize> {
+    buffer: [UnsafeCell<MaybeUninit<T>>; CAP],
+    /// The number of popped elements modulo `2 * CAP`. Written only by the consumer.
+    head: AtomicUsize,
+    /// The number of pushed elements modulo `2 * CAP`. Written only by the producer.
+    tail: AtomicUsize,
+}
+
+// The producer and the consumer never access the same slot at the same time.
+unsafe impl<T: Send, const CAP: usize> Sync for ArrayQueue<T, CAP> {}
+
+impl<T, const CAP: usize> ArrayQueue<T, CAP> {
+    pub const fn new() -> Self {
+        assert!(CAP > 0, "the capacity of ArrayQueue must be greater than 0.");
+        Self {
+            buffer: [const { UnsafeCell::new(MaybeUninit::uninit()) }; CAP],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Adds `value` to the back. Returns it back if the queue is full. Producer only.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        // Acquire: the consumer has finished reading the slot which is reused here.
+        let head = self.head.load(Ordering::Acquire);
+        if Self::distance(head, tail) == CAP {
+            return Err(value);
+        }
+
+        unsafe { (*self.buffer[tail % CAP].get()).write(value) };
+        // Release: the slot is written before the consumer sees the new tail.
+        self.tail.store(Self::next(tail), Ordering::Release);
+        Ok(())
+    }
+
+    /// Removes the front element. Consumer only.
+    pub fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        // Acquire: the producer has finished writing the slot which is read here.
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+
+        let value = unsafe { (*self.buffer[head % CAP].get()).assume_init_read() };
+        // Release: the slot is read before the producer sees it free.
+        self.head.store(Self::next(head), Ordering::Release);
+        Some(value)
+    }
+
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        Self::distance(head, tail)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == CAP
+    }
+
+    pub const fn capacity(&self) -> usize {
+        CAP
+    }
+
+    /// The counter after `counter`.
+    fn next(counter: usize) -> usize {
+        if counter + 1 == 2 * CAP {
+            0
+        } else {
+            counter + 1
+        }
+    }
+
+    /// The number of elements between the counters `head` and `tail`.
+    fn distance(head: usize, tail: usize) -> usize {
+        if tail >= head {
+            tail - head
+        } else {
+            tail + 2 * CAP - head
+        }
+    }
+}
+
+impl<T, const CAP: usize> Default for ArrayQueue<T, CAP> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const CAP: usize> Drop for ArrayQueue<T, CAP> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use std::thread;
+
+    #[test]
+    fn empty_queue_pops_nothing() {
+        let queue = ArrayQueue::<u32, 4>::new();
+        assert!(queue.is_empty());
+        assert!(!queue.is_full());
+        assert_eq!(queue.len(), 0);
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn full_queue_returns_the_value() {
+        let queue = ArrayQueue::<u32, 4>::new();
+        for i in 0..4 {
+            assert_eq!(queue.push(i), Ok(()));
+        }
+        assert!(queue.is_full());
+        assert_eq!(queue.len(), 4);
+        assert_eq!(queue.push(4), Err(4));
+
+        // one pop makes room for one push
+        assert_eq!(queue.pop(), Some(0));
+        assert_eq!(queue.push(4), Ok(()));
+        assert_eq!(queue.push(5), Err(5));
+    }
+
+    #[test]
+    fn pops_in_pushed_order() {
+        let queue = ArrayQueue::<u32, 4>::new();
+        for i in 0..3 {
+            queue.push(i).unwrap();
+        }
+        assert_eq!(queue.pop(), Some(0));
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn wraps_around_the_buffer_and_the_counters() {
+        // 3 doesn't divide a power of two, so a counter wrapping at usize::MAX would skip a slot.
+        let queue = ArrayQueue::<usize, 3>::new();
+        let mut next_pop = 0;
+        for i in 0..100 {
+            queue.push(i).unwrap();
+            if i % 2 == 1 {
+                // keep one or two elements in the queue across the wrap
+                assert_eq!(queue.pop(), Some(next_pop));
+                next_pop += 1;
+            }
+            if queue.is_full() {
+                assert_eq!(queue.len(), 3);
+                assert_eq!(queue.pop(), Some(next_pop));
+                next_pop += 1;
+            }
+        }
+        while let Some(value) = queue.pop() {
+            assert_eq!(value, next_pop);
+            next_pop += 1;
+        }
+        assert_eq!(next_pop, 100);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn drop_drops_remaining_elements() {
+        let counter = Rc::new(());
+        {
+            let queue = ArrayQueue::<Rc<()>, 4>::new();
+            for _ in 0..3 {
+                queue.push(counter.clone()).unwrap();
+            }
+            drop(queue.pop());
+            assert_eq!(Rc::strong_count(&counter), 3);
+        }
+        assert_eq!(Rc::strong_count(&counter), 1);
+    }
+
+    #[test]
+    fn passes_values_between_threads() {
+        const COUNT: usize = 100_000;
+        let queue = ArrayQueue::<usize, 8>::new();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in 0..COUNT {
+                    let mut value = i;
+                    while let Err(rejected) = queue.push(value) {
+                        value = rejected;
+                        thread::yield_now();
+                    }
+                }
+            });
+
+            let mut expected = 0;
+            while expected < COUNT {
+                match queue.pop() {
+                    Some(value) => {
+                        assert_eq!(value, expected);
+                        expected += 1;
+                    }
+                    None => thread::yield_now(),
+                }
+            }
+        });
+        assert!(queue.is_empty());
+    }
+}
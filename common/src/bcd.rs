@@ -0,0 +1,38 @@
+// Binary-coded decimal, used by the CMOS RTC.
+// Each nibble of a byte holds a decimal digit, so a byte holds 0-99.
+
+/// Converts a two-digit BCD byte (0x00-0x99) to binary.
+pub const fn bcd_to_binary(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0f)
+}
+
+/// Converts a binary value (0-99) to a two-digit BCD byte.
+pub const fn binary_to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_every_two_digit_value() {
+        for tens in 0..10 {
+            for ones in 0..10 {
+                assert_eq!(bcd_to_binary(tens << 4 | ones), tens * 10 + ones);
+            }
+        }
+    }
+
+    #[test]
+    fn encodes_every_two_digit_value() {
+        assert_eq!(binary_to_bcd(0), 0x00);
+        assert_eq!(binary_to_bcd(9), 0x09);
+        assert_eq!(binary_to_bcd(10), 0x10);
+        assert_eq!(binary_to_bcd(59), 0x59);
+        assert_eq!(binary_to_bcd(99), 0x99);
+        for value in 0..100 {
+            assert_eq!(bcd_to_binary(binary_to_bcd(value)), value);
+        }
+    }
+}
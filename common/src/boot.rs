@@ -1,27 +1,64 @@
-use uefi::mem::memory_map::MemoryMapOwned;
-
-use crate::{address::PhysPtr, graphic::GraphicInfo};
+use crate::{address::PhysPtr, graphic::GraphicInfo, memory_map::MemoryMap};
 
 pub struct BootInfo {
     pub graphic_info: GraphicInfo,
-    pub memory_map: MemoryMapOwned,
+    pub memory_map: MemoryMap,
     pub rsdp_addr: Option<PhysPtr>,
+    pub cmdline: CmdLine,
 }
 
 impl BootInfo {
     pub fn new(
         graphic_info: GraphicInfo,
-        memory_map: MemoryMapOwned,
+        memory_map: MemoryMap,
         rsdp_addr: Option<PhysPtr>,
+        cmdline: CmdLine,
     ) -> Self {
         Self {
             graphic_info,
             memory_map,
             rsdp_addr,
+            cmdline,
         }
     }
 }
 
+pub const CMDLINE_CAPACITY: usize = 1024;
+
+/// The kernel command line: `key=value` pairs separated by new lines.
+/// Stored inline so that it stays valid after the bootloader's memory is gone.
+#[derive(Clone, Copy)]
+pub struct CmdLine {
+    bytes: [u8; CMDLINE_CAPACITY],
+    len: usize,
+}
+
+impl CmdLine {
+    pub const fn empty() -> Self {
+        Self {
+            bytes: [0; CMDLINE_CAPACITY],
+            len: 0,
+        }
+    }
+
+    /// Returns `None` if `s` doesn't fit in `CMDLINE_CAPACITY` bytes.
+    pub fn new(s: &str) -> Option<Self> {
+        if s.len() > CMDLINE_CAPACITY {
+            return None;
+        }
+
+        let mut cmdline = Self::empty();
+        cmdline.bytes[..s.len()].copy_from_slice(s.as_bytes());
+        cmdline.len = s.len();
+        Some(cmdline)
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only created from &str
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
 pub struct Kernel {
     base_addr: u64,
     entry_point_addr: u64,
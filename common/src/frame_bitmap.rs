@@ -0,0 +1,589 @@
+// A bitmap of physical memory frames and the allocation policies on it.
+// The kernel's frame manager (kernel::phys_mem_manager) is a `FrameBitmap` covering all physical memory.
+
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryManagerError {
+    OutOfMemory { requested: usize, available: usize },
+    ZeroFrames,
+    OutOfRange { frame: usize },
+    DoubleFree { frame: usize },
+}
+
+impl fmt::Display for MemoryManagerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::OutOfMemory {
+                requested,
+                available,
+            } => write!(
+                f,
+                "Out of memory: {} frames were requested but only {} frames are free.",
+                requested, available
+            ),
+            Self::ZeroFrames => write!(f, "The number of frames must be greater than zero."),
+            Self::OutOfRange { frame } => write!(f, "Frame {} is out of the managed range.", frame),
+            Self::DoubleFree { frame } => write!(f, "Frame {} is already free.", frame),
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, MemoryManagerError>;
+
+pub const BYTES_PER_FRAME: usize = 4 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct FrameID(usize);
+impl FrameID {
+    pub const fn new(id: usize) -> Self {
+        Self(id)
+    }
+
+    pub fn get(&self) -> usize {
+        self.0
+    }
+
+    /// The physical address of the first byte of this frame
+    pub fn start_address(&self) -> u64 {
+        (self.0 * BYTES_PER_FRAME) as u64
+    }
+}
+impl Deref for FrameID {
+    type Target = usize;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+impl DerefMut for FrameID {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+type MapLine = usize;
+pub const BITS_PER_MAP_LINE: usize = 8 * core::mem::size_of::<MapLine>();
+
+/// How `FrameBitmap::alloc` chooses a run of free frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationStrategy {
+    /// Takes the first run which fits, searching from just after the last allocation. Fast.
+    NextFit,
+    /// Takes the smallest run which fits to reduce fragmentation. Scans the whole bitmap.
+    BestFit,
+}
+
+const DEFAULT_ALLOCATION_STRATEGY: AllocationStrategy = AllocationStrategy::NextFit;
+
+/// One bit per frame, set if the frame is allocated. Only frames in `begin..end` are handed out.
+pub struct FrameBitmap<const LINES: usize> {
+    alloc_map: [MapLine; LINES],
+    begin: FrameID,
+    end: FrameID,
+    /// Where the next search starts (next-fit). Points just after the last allocation.
+    cursor: FrameID,
+    /// The number of free frames in `begin..end`.
+    free_frame_count: usize,
+    strategy: AllocationStrategy,
+}
+
+impl<const LINES: usize> Default for FrameBitmap<LINES> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const LINES: usize> FrameBitmap<LINES> {
+    /// The number of frames the bitmap can manage
+    pub const FRAME_COUNT: usize = LINES * BITS_PER_MAP_LINE;
+
+    /// Creates a bitmap where every frame is free but none is managed until `set_memory_range` is called.
+    pub const fn new() -> Self {
+        Self {
+            alloc_map: [0; LINES],
+            begin: FrameID(0),
+            end: FrameID(Self::FRAME_COUNT),
+            cursor: FrameID(0),
+            free_frame_count: 0,
+            strategy: DEFAULT_ALLOCATION_STRATEGY,
+        }
+    }
+
+    pub fn set_strategy(&mut self, strategy: AllocationStrategy) {
+        self.strategy = strategy;
+    }
+
+    pub fn strategy(&self) -> AllocationStrategy {
+        self.strategy
+    }
+
+    /// Marks `count` frames from `first_frame_id` as allocated. For setting up the bitmap before
+    /// `set_memory_range`, which counts the free frames.
+    pub fn mark_allocated(&mut self, first_frame_id: FrameID, count: usize) {
+        self.set_bits(first_frame_id, count, true);
+    }
+
+    /// Sets the range of frames to allocate from. `range_end` is capped at `FRAME_COUNT`.
+    pub fn set_memory_range(&mut self, range_begin: FrameID, range_end: FrameID) {
+        self.begin = range_begin;
+        self.end = FrameID(range_end.get().min(Self::FRAME_COUNT));
+        self.cursor = FrameID(self.begin.get());
+        self.free_frame_count = self.count_free_frames(self.begin.get(), self.end.get());
+    }
+
+    /// Sets `count` bits from `first_frame_id`, a whole map line at a time where possible.
+    /// Frames beyond the bitmap are ignored.
+    fn set_bits(&mut self, first_frame_id: FrameID, count: usize, allocated: bool) {
+        let mut frame_id = first_frame_id.get();
+        let end = (first_frame_id.get() + count).min(Self::FRAME_COUNT);
+
+        while frame_id < end {
+            let line_index = frame_id / BITS_PER_MAP_LINE;
+            let bit_index = frame_id % BITS_PER_MAP_LINE;
+            let bit_count = (BITS_PER_MAP_LINE - bit_index).min(end - frame_id);
+
+            let mask = if bit_count == BITS_PER_MAP_LINE {
+                MapLine::MAX
+            } else {
+                ((1 << bit_count) - 1) << bit_index
+            };
+
+            if allocated {
+                self.alloc_map[line_index] |= mask;
+            } else {
+                self.alloc_map[line_index] &= !mask;
+            }
+
+            frame_id += bit_count;
+        }
+    }
+
+    fn get_bit(&self, frame_id: FrameID) -> bool {
+        let line_index = frame_id.get() / BITS_PER_MAP_LINE;
+        let bit_index = frame_id.get() % BITS_PER_MAP_LINE;
+
+        (self.alloc_map[line_index] & (1 << bit_index)) != 0
+    }
+
+    fn count_free_frames(&self, begin: usize, end: usize) -> usize {
+        let mut count = 0;
+        let mut frame_id = begin;
+        while frame_id < end {
+            let line_index = frame_id / BITS_PER_MAP_LINE;
+            let bit_index = frame_id % BITS_PER_MAP_LINE;
+            let bit_count = (BITS_PER_MAP_LINE - bit_index).min(end - frame_id);
+
+            let mask = if bit_count == BITS_PER_MAP_LINE {
+                MapLine::MAX
+            } else {
+                (1 << bit_count) - 1
+            };
+            count += (!(self.alloc_map[line_index] >> bit_index) & mask).count_ones() as usize;
+
+            frame_id += bit_count;
+        }
+        count
+    }
+
+    /// Returns the number of consecutive free frames from `frame_id`, up to `max`.
+    fn free_run_length(&self, frame_id: usize, max: usize) -> usize {
+        let mut length = 0;
+        while length < max {
+            let line_index = (frame_id + length) / BITS_PER_MAP_LINE;
+            let bit_index = (frame_id + length) % BITS_PER_MAP_LINE;
+            let line = self.alloc_map[line_index] >> bit_index;
+
+            let free_bits = if line == 0 {
+                BITS_PER_MAP_LINE - bit_index
+            } else {
+                line.trailing_zeros() as usize
+            };
+            length += free_bits;
+
+            if line != 0 {
+                break;
+            }
+        }
+        length.min(max)
+    }
+
+    /// Finds `number_of_frame` consecutive free frames starting in `start..` and ending by `limit`.
+    fn find_free_frames(
+        &self,
+        start: usize,
+        limit: usize,
+        number_of_frame: usize,
+    ) -> Option<FrameID> {
+        let mut frame_id = start;
+        while frame_id + number_of_frame <= limit {
+            // Treat the bits below `frame_id` as allocated and skip lines which have no free frame.
+            let line_index = frame_id / BITS_PER_MAP_LINE;
+            let bit_index = frame_id % BITS_PER_MAP_LINE;
+            let line = self.alloc_map[line_index] | ((1 << bit_index) - 1);
+            if line == MapLine::MAX {
+                frame_id = (line_index + 1) * BITS_PER_MAP_LINE;
+                continue;
+            }
+
+            frame_id = line_index * BITS_PER_MAP_LINE + (!line).trailing_zeros() as usize;
+            if frame_id + number_of_frame > limit {
+                return None;
+            }
+
+            let run_length = self.free_run_length(frame_id, number_of_frame);
+            // If there are `number_of_frame`-consecutive free frames, following condition is true.
+            if run_length == number_of_frame {
+                return Some(FrameID(frame_id));
+            }
+
+            // The frame right after the run is allocated.
+            frame_id += run_length + 1;
+        }
+        None
+    }
+
+    /// Finds the free frame with the largest id in `begin..start`.
+    fn find_free_frame_backward(&self, start: usize) -> Option<FrameID> {
+        let begin = self.begin.get();
+        let mut frame_id = start;
+        while frame_id > begin {
+            let last = frame_id - 1;
+            let line_index = last / BITS_PER_MAP_LINE;
+            let bit_index = last % BITS_PER_MAP_LINE;
+            // Treat the bits above `last` as allocated.
+            let mask = if bit_index == BITS_PER_MAP_LINE - 1 {
+                MapLine::MAX
+            } else {
+                (1 << (bit_index + 1)) - 1
+            };
+            let free_bits = !self.alloc_map[line_index] & mask;
+            if free_bits == 0 {
+                frame_id = line_index * BITS_PER_MAP_LINE;
+                continue;
+            }
+
+            let found = line_index * BITS_PER_MAP_LINE + (BITS_PER_MAP_LINE - 1)
+                - free_bits.leading_zeros() as usize;
+            return (found >= begin).then_some(FrameID(found));
+        }
+        None
+    }
+
+    /// Finds the smallest run of free frames in `begin..end` which has `number_of_frame` frames or more.
+    fn find_best_fit_frames(&self, number_of_frame: usize) -> Option<FrameID> {
+        let end = self.end.get();
+        let mut best: Option<(usize, usize)> = None; // (frame id, run length)
+        let mut frame_id = self.begin.get();
+        while frame_id < end {
+            let line_index = frame_id / BITS_PER_MAP_LINE;
+            let bit_index = frame_id % BITS_PER_MAP_LINE;
+            let line = self.alloc_map[line_index] | ((1 << bit_index) - 1);
+            if line == MapLine::MAX {
+                frame_id = (line_index + 1) * BITS_PER_MAP_LINE;
+                continue;
+            }
+
+            frame_id = line_index * BITS_PER_MAP_LINE + (!line).trailing_zeros() as usize;
+            if frame_id >= end {
+                break;
+            }
+
+            let run_length = self.free_run_length(frame_id, end - frame_id);
+            if run_length >= number_of_frame
+                && best.is_none_or(|(_, best_length)| run_length < best_length)
+            {
+                best = Some((frame_id, run_length));
+                // No run can fit tighter than this.
+                if run_length == number_of_frame {
+                    break;
+                }
+            }
+
+            frame_id += run_length + 1;
+        }
+        best.map(|(frame_id, _)| FrameID(frame_id))
+    }
+
+    pub fn alloc(&mut self, number_of_frame: usize) -> Result<FrameID> {
+        if number_of_frame == 0 {
+            return Err(MemoryManagerError::ZeroFrames);
+        }
+        let out_of_memory = MemoryManagerError::OutOfMemory {
+            requested: number_of_frame,
+            available: self.free_frame_count,
+        };
+        if number_of_frame > self.free_frame_count {
+            return Err(out_of_memory);
+        }
+
+        let frame_id = match self.strategy {
+            AllocationStrategy::NextFit => {
+                // Search from the cursor first, then wrap around and search runs which start before it.
+                let cursor = self.cursor.get().max(self.begin.get());
+                self.find_free_frames(cursor, self.end.get(), number_of_frame)
+                    .or_else(|| {
+                        let limit = (cursor + number_of_frame - 1).min(self.end.get());
+                        self.find_free_frames(self.begin.get(), limit, number_of_frame)
+                    })
+            }
+            AllocationStrategy::BestFit => self.find_best_fit_frames(number_of_frame),
+        }
+        // There are enough free frames in total but they are fragmented.
+        .ok_or(out_of_memory)?;
+
+        self.mark_allocated(FrameID(frame_id.get()), number_of_frame);
+        self.free_frame_count -= number_of_frame;
+        self.cursor = FrameID(frame_id.get() + number_of_frame);
+        Ok(frame_id)
+    }
+
+    /// Allocates a single frame as close to `hint` as possible, searching outward from it.
+    /// Useful to keep frames which are used together, such as page tables, near each other.
+    pub fn alloc_near(&mut self, hint: FrameID) -> Result<FrameID> {
+        if self.free_frame_count == 0 {
+            return Err(MemoryManagerError::OutOfMemory {
+                requested: 1,
+                available: 0,
+            });
+        }
+
+        let hint = hint.get().clamp(self.begin.get(), self.end.get());
+        let above = self.find_free_frames(hint, self.end.get(), 1);
+        let below = self.find_free_frame_backward(hint);
+        let frame_id = match (above, below) {
+            (Some(above), Some(below)) => {
+                if above.get() - hint <= hint - below.get() {
+                    above
+                } else {
+                    below
+                }
+            }
+            (Some(frame_id), None) | (None, Some(frame_id)) => frame_id,
+            (None, None) => {
+                return Err(MemoryManagerError::OutOfMemory {
+                    requested: 1,
+                    available: self.free_frame_count,
+                });
+            }
+        };
+
+        self.mark_allocated(FrameID(frame_id.get()), 1);
+        self.free_frame_count -= 1;
+        Ok(frame_id)
+    }
+
+    /// Frees frames allocated by `alloc` or `alloc_near`. The bitmap is left untouched if any frame in the range
+    /// is out of the managed range or already free.
+    pub fn free(&mut self, first_frame_id: FrameID, number_of_frame: usize) -> Result<()> {
+        if number_of_frame == 0 {
+            return Err(MemoryManagerError::ZeroFrames);
+        }
+
+        let begin = first_frame_id.get();
+        let end = begin.saturating_add(number_of_frame);
+        if begin < self.begin.get() {
+            return Err(MemoryManagerError::OutOfRange { frame: begin });
+        }
+        if end > self.end.get() {
+            return Err(MemoryManagerError::OutOfRange {
+                frame: self.end.get().max(begin),
+            });
+        }
+        if self.count_free_frames(begin, end) != 0 {
+            let frame = (begin..end)
+                .find(|&frame_id| !self.get_bit(FrameID(frame_id)))
+                .unwrap_or(begin);
+            return Err(MemoryManagerError::DoubleFree { frame });
+        }
+
+        self.set_bits(FrameID(begin), number_of_frame, false);
+        self.free_frame_count += number_of_frame;
+
+        if begin < self.cursor.get() {
+            self.cursor = first_frame_id;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of frames which can be allocated now.
+    pub fn free_frame_count(&self) -> usize {
+        self.free_frame_count
+    }
+
+    /// Returns the number of frames managed by this manager, whether allocated or not.
+    pub fn total_frame_count(&self) -> usize {
+        self.end.get() - self.begin.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 64 frames whose free runs are 0..8 (8 frames) and 10..12 (2 frames).
+    fn fragmented() -> FrameBitmap<1> {
+        let mut bitmap = FrameBitmap::new();
+        bitmap.mark_allocated(FrameID::new(8), 2);
+        bitmap.mark_allocated(FrameID::new(12), 52);
+        bitmap.set_memory_range(FrameID::new(0), FrameID::new(64));
+        bitmap
+    }
+
+    #[test]
+    fn set_memory_range_counts_free_frames() {
+        let bitmap = fragmented();
+        assert_eq!(bitmap.free_frame_count(), 10);
+        assert_eq!(bitmap.total_frame_count(), 64);
+    }
+
+    #[test]
+    fn best_fit_picks_a_tighter_run_than_next_fit() {
+        let mut next_fit = fragmented();
+        assert_eq!(next_fit.strategy(), AllocationStrategy::NextFit);
+        assert_eq!(next_fit.alloc(2), Ok(FrameID::new(0)));
+
+        let mut best_fit = fragmented();
+        best_fit.set_strategy(AllocationStrategy::BestFit);
+        assert_eq!(best_fit.alloc(2), Ok(FrameID::new(10)));
+        // the 8 frame run is left for a larger request
+        assert_eq!(best_fit.alloc(8), Ok(FrameID::new(0)));
+        assert_eq!(best_fit.free_frame_count(), 0);
+    }
+
+    #[test]
+    fn next_fit_continues_after_the_last_allocation() {
+        let mut bitmap = fragmented();
+        assert_eq!(bitmap.alloc(3), Ok(FrameID::new(0)));
+        assert_eq!(bitmap.alloc(3), Ok(FrameID::new(3)));
+        // 6..8 is too short, so the search goes on to 10..12
+        assert_eq!(bitmap.alloc(2), Ok(FrameID::new(6)));
+        assert_eq!(bitmap.alloc(2), Ok(FrameID::new(10)));
+    }
+
+    #[test]
+    fn alloc_fails_when_no_run_is_long_enough() {
+        for strategy in [AllocationStrategy::NextFit, AllocationStrategy::BestFit] {
+            let mut bitmap = fragmented();
+            bitmap.set_strategy(strategy);
+            assert_eq!(
+                bitmap.alloc(9),
+                Err(MemoryManagerError::OutOfMemory {
+                    requested: 9,
+                    available: 10
+                })
+            );
+            assert_eq!(bitmap.alloc(0), Err(MemoryManagerError::ZeroFrames));
+            assert_eq!(bitmap.free_frame_count(), 10);
+        }
+    }
+
+    #[test]
+    fn runs_can_cross_map_lines() {
+        let mut bitmap = FrameBitmap::<2>::new();
+        bitmap.mark_allocated(FrameID::new(0), 60);
+        bitmap.set_memory_range(FrameID::new(0), FrameID::new(128));
+        assert_eq!(bitmap.alloc(10), Ok(FrameID::new(60)));
+        assert_eq!(bitmap.free_frame_count(), 58);
+    }
+
+    /// 64 frames where only 10, 20 and 30 are free.
+    fn sparse() -> FrameBitmap<1> {
+        let mut bitmap = FrameBitmap::new();
+        bitmap.mark_allocated(FrameID::new(0), 10);
+        bitmap.mark_allocated(FrameID::new(11), 9);
+        bitmap.mark_allocated(FrameID::new(21), 9);
+        bitmap.mark_allocated(FrameID::new(31), 33);
+        bitmap.set_memory_range(FrameID::new(0), FrameID::new(64));
+        bitmap
+    }
+
+    #[test]
+    fn alloc_near_returns_the_closest_free_frame() {
+        // closer below
+        assert_eq!(sparse().alloc_near(FrameID::new(22)), Ok(FrameID::new(20)));
+        // closer above
+        assert_eq!(sparse().alloc_near(FrameID::new(17)), Ok(FrameID::new(20)));
+        // a tie goes above
+        assert_eq!(sparse().alloc_near(FrameID::new(15)), Ok(FrameID::new(20)));
+        // the hint itself
+        assert_eq!(sparse().alloc_near(FrameID::new(10)), Ok(FrameID::new(10)));
+        // only below or only above
+        assert_eq!(sparse().alloc_near(FrameID::new(63)), Ok(FrameID::new(30)));
+        assert_eq!(sparse().alloc_near(FrameID::new(0)), Ok(FrameID::new(10)));
+        // outside of the managed range
+        assert_eq!(
+            sparse().alloc_near(FrameID::new(1000)),
+            Ok(FrameID::new(30))
+        );
+    }
+
+    #[test]
+    fn alloc_near_moves_outward_as_frames_are_taken() {
+        let mut bitmap = sparse();
+        assert_eq!(bitmap.alloc_near(FrameID::new(21)), Ok(FrameID::new(20)));
+        assert_eq!(bitmap.alloc_near(FrameID::new(21)), Ok(FrameID::new(30)));
+        assert_eq!(bitmap.alloc_near(FrameID::new(21)), Ok(FrameID::new(10)));
+        assert_eq!(
+            bitmap.alloc_near(FrameID::new(21)),
+            Err(MemoryManagerError::OutOfMemory {
+                requested: 1,
+                available: 0
+            })
+        );
+        assert_eq!(bitmap.free_frame_count(), 0);
+    }
+
+    #[test]
+    fn free_returns_frames() {
+        let mut bitmap = fragmented();
+        let frame = bitmap.alloc(8).unwrap();
+        assert_eq!(bitmap.free_frame_count(), 2);
+        assert_eq!(bitmap.free(frame, 8), Ok(()));
+        assert_eq!(bitmap.free_frame_count(), 10);
+        // the frames can be allocated again
+        assert_eq!(bitmap.alloc(8), Ok(FrameID::new(0)));
+    }
+
+    #[test]
+    fn free_rejects_frames_outside_of_the_range() {
+        let mut bitmap = FrameBitmap::<1>::new();
+        bitmap.mark_allocated(FrameID::new(0), 64);
+        bitmap.set_memory_range(FrameID::new(1), FrameID::new(32));
+
+        assert_eq!(
+            bitmap.free(FrameID::new(0), 1),
+            Err(MemoryManagerError::OutOfRange { frame: 0 })
+        );
+        assert_eq!(
+            bitmap.free(FrameID::new(30), 4),
+            Err(MemoryManagerError::OutOfRange { frame: 32 })
+        );
+        assert_eq!(
+            bitmap.free(FrameID::new(40), 1),
+            Err(MemoryManagerError::OutOfRange { frame: 40 })
+        );
+        assert_eq!(
+            bitmap.free(FrameID::new(1), 0),
+            Err(MemoryManagerError::ZeroFrames)
+        );
+        assert_eq!(bitmap.free_frame_count(), 0);
+    }
+
+    #[test]
+    fn free_rejects_a_double_free_without_touching_the_bitmap() {
+        let mut bitmap = fragmented();
+        // 8..10 is allocated but 10 is free
+        assert_eq!(
+            bitmap.free(FrameID::new(8), 3),
+            Err(MemoryManagerError::DoubleFree { frame: 10 })
+        );
+        assert_eq!(bitmap.free_frame_count(), 10);
+        // 8 and 9 are still allocated
+        assert_eq!(bitmap.free(FrameID::new(8), 2), Ok(()));
+        assert_eq!(
+            bitmap.free(FrameID::new(8), 1),
+            Err(MemoryManagerError::DoubleFree { frame: 8 })
+        );
+    }
+}
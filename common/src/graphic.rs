@@ -5,12 +5,14 @@ use crate::error::Result;
 #[derive(Debug, Clone, PartialEq)]
 pub enum GraphicError {
     UnsupportedPixelFormat,
+    ColorValueOutOfRange,
 }
 
 impl GraphicError {
     pub fn msg(&self) -> &'static str {
         match *self {
             Self::UnsupportedPixelFormat => "Unsupported pixel format.",
+            Self::ColorValueOutOfRange => "The color value must be in 0x000000 ~ 0xFFFFFF.",
         }
     }
 }
@@ -96,78 +98,228 @@ impl GraphicInfo {
 //     }
 // }
 
-#[derive(Clone, Copy, Debug)]
-pub struct Pixel(u32);
+/// A 24 bit color. The only color type: conversions to the byte order of a frame buffer are done by
+/// `get_rgb_le` and `get_bgr_le`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RgbColor(u32);
 
-impl Pixel {
-    pub fn new() -> Self {
+impl RgbColor {
+    pub const fn new() -> Self {
         Self(0x00000000)
     }
 
-    pub fn rgb(r: u8, g: u8, b: u8) -> Self {
-        Self(u32::from_be_bytes([r, g, b, 0x0]))
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self(u32::from_be_bytes([r, g, b, 0x00]))
+    }
+
+    /// Creates a color from a `0xRRGGBB` value. The most significant byte is ignored.
+    pub const fn from_u32(value: u32) -> Self {
+        Self((value & 0xffffff) << 8)
     }
 
-    pub fn get(&self) -> u32 {
+    /// Creates a color from a `0xRRGGBB` value.
+    /// Unlike `from_u32`, returns an error if the most significant byte is not 0.
+    pub fn try_from_u32(value: u32) -> Result<Self> {
+        if value > 0xffffff {
+            return Err(GraphicError::ColorValueOutOfRange.into());
+        }
+        Ok(Self::from_u32(value))
+    }
+
+    /// Returns the color as a `0xRRGGBB` value.
+    pub const fn to_u32(&self) -> u32 {
+        self.0 >> 8
+    }
+
+    pub const fn get(&self) -> u32 {
         self.0
     }
 
-    pub fn bgr(&mut self) {
-        let r = ((self.0 & 0xff000000) >> 0x18) as u8;
-        let g = ((self.0 & 0xff0000) >> 0x10) as u8;
-        let b = ((self.0 & 0xff00) >> 0x8) as u8;
-        self.0 = u32::from_be_bytes([b, g, r, 0]);
+    pub const fn r(&self) -> u8 {
+        (self.0 >> 24) as u8
     }
 
-    pub fn le(&self) -> u32 {
-        u32::from_be(self.0)
+    pub const fn g(&self) -> u8 {
+        (self.0 >> 16) as u8
     }
-}
 
-impl From<u32> for Pixel {
-    fn from(value: u32) -> Self {
-        Self(value)
+    pub const fn b(&self) -> u8 {
+        (self.0 >> 8) as u8
     }
-}
 
-impl From<RgbColor> for Pixel {
-    fn from(value: RgbColor) -> Self {
-        Self(value.get() & 0xFFFFFF00)
+    /// Returns the value to store into a frame buffer of `PixelFormat::Rgb`.
+    /// Red is placed at the lowest address: `0x00BBGGRR` when read as a little endian u32.
+    pub const fn get_rgb_le(&self) -> u32 {
+        u32::from_le_bytes([self.r(), self.g(), self.b(), 0])
     }
-}
 
-#[derive(Clone, Copy, Debug)]
-pub struct RgbColor(u32);
+    /// Returns the value to store into a frame buffer of `PixelFormat::Bgr`.
+    /// Blue is placed at the lowest address: `0x00RRGGBB` when read as a little endian u32.
+    pub const fn get_bgr_le(&self) -> u32 {
+        u32::from_le_bytes([self.b(), self.g(), self.r(), 0])
+    }
 
-impl RgbColor {
-    pub const fn new() -> Self {
-        Self(0x00000000)
+    /// Inverse of `get_bgr_le`.
+    pub const fn from_bgr_le(value: u32) -> Self {
+        let [b, g, r, _] = value.to_le_bytes();
+        Self::rgb(r, g, b)
     }
 
-    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
-        Self(u32::from_be_bytes([r, g, b, 0x00]))
+    /// Inverse of `get_rgb_le`.
+    pub const fn from_rgb_le(value: u32) -> Self {
+        let [r, g, b, _] = value.to_le_bytes();
+        Self::rgb(r, g, b)
     }
 
-    pub const fn get(&self) -> u32 {
-        self.0
+    /// Linearly interpolates between `self` (alpha = 0) and `other` (alpha = 255).
+    pub const fn lerp(self, other: Self, alpha: u8) -> Self {
+        const fn lerp_channel(from: u8, to: u8, alpha: u8) -> u8 {
+            let alpha = alpha as u32;
+            ((from as u32 * (255 - alpha) + to as u32 * alpha + 127) / 255) as u8
+        }
+
+        Self::rgb(
+            lerp_channel(self.r(), other.r(), alpha),
+            lerp_channel(self.g(), other.g(), alpha),
+            lerp_channel(self.b(), other.b(), alpha),
+        )
     }
 
-    pub const fn to_bgr(&mut self) {
-        let r = ((self.0 & 0xff000000) >> 0x18) as u8;
-        let g = ((self.0 & 0xff0000) >> 0x10) as u8;
-        let b = ((self.0 & 0xff00) >> 0x8) as u8;
-        self.0 = u32::from_be_bytes([b, g, r, 0])
+    /// Returns `(hue, saturation, value)`. hue is in 0..360 degrees, saturation and value are in 0..=255.
+    pub const fn to_hsv(&self) -> (u16, u8, u8) {
+        let (r, g, b) = (self.r() as i32, self.g() as i32, self.b() as i32);
+        let max = if r > g { if r > b { r } else { b } } else if g > b { g } else { b };
+        let min = if r < g { if r < b { r } else { b } } else if g < b { g } else { b };
+        let delta = max - min;
+
+        let saturation = if max == 0 {
+            0
+        } else {
+            (delta * 255 + max / 2) / max
+        };
+
+        let hue = if delta == 0 {
+            0
+        } else if max == r {
+            (60 * (g - b) + delta / 2).div_euclid(delta)
+        } else if max == g {
+            120 + (60 * (b - r) + delta / 2).div_euclid(delta)
+        } else {
+            240 + (60 * (r - g) + delta / 2).div_euclid(delta)
+        };
+
+        (hue.rem_euclid(360) as u16, saturation as u8, max as u8)
     }
-}
 
-impl From<u32> for RgbColor {
-    fn from(value: u32) -> Self {
-        Self(value)
+    /// Inverse of `to_hsv`. `hue` is taken modulo 360.
+    pub const fn from_hsv(hue: u16, saturation: u8, value: u8) -> Self {
+        if saturation == 0 {
+            return Self::rgb(value, value, value);
+        }
+
+        let hue = (hue % 360) as u32;
+        let (s, v) = (saturation as u32, value as u32);
+        let remainder = hue % 60;
+
+        let p = (v * (255 - s) / 255) as u8;
+        let q = (v * (255 * 60 - s * remainder) / (255 * 60)) as u8;
+        let t = (v * (255 * 60 - s * (60 - remainder)) / (255 * 60)) as u8;
+        let v = value;
+
+        match hue / 60 {
+            0 => Self::rgb(v, t, p),
+            1 => Self::rgb(q, v, p),
+            2 => Self::rgb(p, v, t),
+            3 => Self::rgb(p, q, v),
+            4 => Self::rgb(t, p, v),
+            _ => Self::rgb(v, p, q),
+        }
+    }
+
+    /// Moves the color towards white. `amount` = 0 keeps the color and 255 makes it white.
+    pub const fn brighten(self, amount: u8) -> Self {
+        self.lerp(Self::rgb(0xff, 0xff, 0xff), amount)
+    }
+
+    /// Moves the color towards black. `amount` = 0 keeps the color and 255 makes it black.
+    pub const fn darken(self, amount: u8) -> Self {
+        self.lerp(Self::rgb(0, 0, 0), amount)
     }
 }
 
-impl From<Pixel> for RgbColor {
-    fn from(value: Pixel) -> Self {
-        Self(value.get() & 0xFFFFFF00)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLOR: RgbColor = RgbColor::rgb(0x12, 0x34, 0x56);
+
+    #[test]
+    fn u32_round_trip() {
+        assert_eq!(RgbColor::from_u32(0x123456), COLOR);
+        assert_eq!(COLOR.to_u32(), 0x123456);
+        assert_eq!((COLOR.r(), COLOR.g(), COLOR.b()), (0x12, 0x34, 0x56));
+        // the most significant byte is ignored
+        assert_eq!(RgbColor::from_u32(0xff123456), COLOR);
+    }
+
+    #[test]
+    fn try_from_u32_rejects_out_of_range_values() {
+        assert_eq!(RgbColor::try_from_u32(0x123456).ok(), Some(COLOR));
+        assert_eq!(RgbColor::try_from_u32(0xffffff).ok(), Some(RgbColor::rgb(0xff, 0xff, 0xff)));
+        assert!(RgbColor::try_from_u32(0x1000000).is_err());
+    }
+
+    #[test]
+    fn rgb_frame_buffer_byte_order() {
+        // red at the lowest address
+        assert_eq!(COLOR.get_rgb_le().to_le_bytes(), [0x12, 0x34, 0x56, 0x00]);
+        assert_eq!(RgbColor::from_rgb_le(COLOR.get_rgb_le()), COLOR);
+    }
+
+    #[test]
+    fn bgr_frame_buffer_byte_order() {
+        // blue at the lowest address
+        assert_eq!(COLOR.get_bgr_le().to_le_bytes(), [0x56, 0x34, 0x12, 0x00]);
+        assert_eq!(RgbColor::from_bgr_le(COLOR.get_bgr_le()), COLOR);
+    }
+
+    const RED: RgbColor = RgbColor::rgb(0xff, 0, 0);
+    const WHITE: RgbColor = RgbColor::rgb(0xff, 0xff, 0xff);
+    const GRAY: RgbColor = RgbColor::rgb(0x80, 0x80, 0x80);
+    const BLACK: RgbColor = RgbColor::rgb(0, 0, 0);
+
+    #[test]
+    fn hsv_round_trip() {
+        assert_eq!(RED.to_hsv(), (0, 255, 255));
+        assert_eq!(WHITE.to_hsv(), (0, 0, 255));
+        assert_eq!(GRAY.to_hsv(), (0, 0, 0x80));
+
+        for color in [RED, WHITE, GRAY, BLACK] {
+            let (hue, saturation, value) = color.to_hsv();
+            assert_eq!(RgbColor::from_hsv(hue, saturation, value), color);
+        }
+    }
+
+    #[test]
+    fn from_hsv_takes_hue_modulo_360() {
+        assert_eq!(RgbColor::from_hsv(360, 255, 255), RED);
+        assert_eq!(RgbColor::from_hsv(120, 255, 255), RgbColor::rgb(0, 0xff, 0));
+        assert_eq!(RgbColor::from_hsv(240, 255, 255), RgbColor::rgb(0, 0, 0xff));
+    }
+
+    #[test]
+    fn brightness_clamps_at_black_and_white() {
+        assert_eq!(COLOR.brighten(0), COLOR);
+        assert_eq!(COLOR.brighten(255), WHITE);
+        assert_eq!(WHITE.brighten(128), WHITE);
+
+        assert_eq!(COLOR.darken(0), COLOR);
+        assert_eq!(COLOR.darken(255), BLACK);
+        assert_eq!(BLACK.darken(128), BLACK);
+
+        let brighter = GRAY.brighten(128);
+        assert!(brighter.r() > GRAY.r() && brighter.r() < 0xff);
+        let darker = GRAY.darken(128);
+        assert!(darker.r() < GRAY.r() && darker.r() > 0);
     }
 }
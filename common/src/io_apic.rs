@@ -0,0 +1,165 @@
+// The I/O APIC redirection table entry. The registers are accessed by the kernel (kernel::arch::IoApic);
+// the bit layout is here so that it can be tested on the host.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryMode {
+    Fixed = 0b000,
+    LowestPriority = 0b001,
+    Smi = 0b010,
+    Nmi = 0b100,
+    Init = 0b101,
+    ExtInt = 0b111,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DestinationMode {
+    Physical = 0,
+    Logical = 1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh = 0,
+    ActiveLow = 1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    Edge = 0,
+    Level = 1,
+}
+
+/// An entry of the I/O APIC redirection table.
+/// https://wiki.osdev.org/IOAPIC#IOREDTBL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedirectionEntry(u64);
+
+impl RedirectionEntry {
+    /// Creates an entry which delivers `vector` as a fixed, edge-triggered, active high and
+    /// unmasked interrupt to the local APIC whose id is 0 in physical destination mode.
+    pub const fn new(vector: u8) -> Self {
+        Self(vector as u64)
+    }
+
+    pub const fn get(&self) -> u64 {
+        self.0
+    }
+
+    const fn with_bits(self, shift: u32, width: u32, value: u64) -> Self {
+        let mask = ((1 << width) - 1) << shift;
+        Self((self.0 & !mask) | ((value << shift) & mask))
+    }
+
+    /// Bit 0-7: Vector
+    pub const fn vector(self, vector: u8) -> Self {
+        self.with_bits(0, 8, vector as u64)
+    }
+
+    /// Bit 8-10: Delivery Mode
+    pub const fn delivery_mode(self, delivery_mode: DeliveryMode) -> Self {
+        self.with_bits(8, 3, delivery_mode as u64)
+    }
+
+    /// Bit 11: Destination Mode
+    pub const fn destination_mode(self, destination_mode: DestinationMode) -> Self {
+        self.with_bits(11, 1, destination_mode as u64)
+    }
+
+    /// Bit 13: Pin Polarity
+    pub const fn polarity(self, polarity: Polarity) -> Self {
+        self.with_bits(13, 1, polarity as u64)
+    }
+
+    /// Bit 15: Trigger Mode
+    pub const fn trigger_mode(self, trigger_mode: TriggerMode) -> Self {
+        self.with_bits(15, 1, trigger_mode as u64)
+    }
+
+    /// Bit 16: Interrupt Mask. Stops the interrupt from reaching the processor if set.
+    pub const fn masked(self, masked: bool) -> Self {
+        self.with_bits(16, 1, masked as u64)
+    }
+
+    /// Bit 56-63: Destination (a local APIC ID in physical destination mode)
+    pub const fn destination(self, destination: u8) -> Self {
+        self.with_bits(56, 8, destination as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_sets_only_the_vector() {
+        assert_eq!(RedirectionEntry::new(0x21).get(), 0x21);
+    }
+
+    #[test]
+    fn fields_are_at_their_bits() {
+        let entry = RedirectionEntry::new(0)
+            .vector(0x2c)
+            .delivery_mode(DeliveryMode::ExtInt)
+            .destination_mode(DestinationMode::Logical)
+            .polarity(Polarity::ActiveLow)
+            .trigger_mode(TriggerMode::Level)
+            .masked(true)
+            .destination(0xa5);
+        let expected = 0x2c // vector
+            | 0b111 << 8 // delivery mode
+            | 1 << 11 // destination mode
+            | 1 << 13 // polarity
+            | 1 << 15 // trigger mode
+            | 1 << 16 // mask
+            | 0xa5 << 56; // destination
+        assert_eq!(entry.get(), expected);
+    }
+
+    #[test]
+    fn each_field_leaves_the_others_alone() {
+        let all_set = RedirectionEntry::new(0xff)
+            .delivery_mode(DeliveryMode::ExtInt)
+            .destination_mode(DestinationMode::Logical)
+            .polarity(Polarity::ActiveLow)
+            .trigger_mode(TriggerMode::Level)
+            .masked(true)
+            .destination(0xff);
+
+        assert_eq!(all_set.vector(0).get(), all_set.get() & !0xff);
+        assert_eq!(
+            all_set.delivery_mode(DeliveryMode::Fixed).get(),
+            all_set.get() & !(0b111 << 8)
+        );
+        assert_eq!(
+            all_set.destination_mode(DestinationMode::Physical).get(),
+            all_set.get() & !(1 << 11)
+        );
+        assert_eq!(
+            all_set.polarity(Polarity::ActiveHigh).get(),
+            all_set.get() & !(1 << 13)
+        );
+        assert_eq!(
+            all_set.trigger_mode(TriggerMode::Edge).get(),
+            all_set.get() & !(1 << 15)
+        );
+        assert_eq!(all_set.masked(false).get(), all_set.get() & !(1 << 16));
+        assert_eq!(all_set.destination(0).get(), all_set.get() & !(0xff << 56));
+    }
+
+    #[test]
+    fn delivery_modes() {
+        for (mode, bits) in [
+            (DeliveryMode::Fixed, 0b000),
+            (DeliveryMode::LowestPriority, 0b001),
+            (DeliveryMode::Smi, 0b010),
+            (DeliveryMode::Nmi, 0b100),
+            (DeliveryMode::Init, 0b101),
+            (DeliveryMode::ExtInt, 0b111),
+        ] {
+            assert_eq!(
+                RedirectionEntry::new(0).delivery_mode(mode).get(),
+                bits << 8
+            );
+        }
+    }
+}
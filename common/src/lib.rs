@@ -1,9 +1,15 @@
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
 
 pub mod address;
 pub mod array;
+pub mod arrayqueue;
 pub mod arrayvec;
+pub mod bcd;
 pub mod boot;
 pub mod error;
+pub mod frame_bitmap;
 pub mod graphic;
+pub mod io_apic;
+pub mod memory_map;
+pub mod message;
+pub mod scan_code;
@@ -0,0 +1,107 @@
+use uefi::boot::MemoryType;
+use uefi::mem::memory_map::{MemoryMap as _, MemoryMapOwned};
+
+/// The size of a page in a memory map descriptor. It's always 4 KiB regardless of the paging.
+pub const UEFI_PAGE_SIZE: usize = 1024 * 4;
+
+/// The maximum number of descriptors in `MemoryMap`.
+/// Adjacent descriptors of the same type are merged, so real firmwares fit in this.
+pub const MAX_MEMORY_DESCRIPTORS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum MemoryRegionType {
+    /// Free memory, including memory which was used by UEFI boot services
+    Available,
+    /// Memory used by the bootloader and the kernel (kernel image, BootInfo, ...)
+    Loader,
+    /// ACPI tables. Can be reused after the tables are read.
+    AcpiReclaimable,
+    AcpiNvs,
+    Reserved,
+}
+
+impl From<MemoryType> for MemoryRegionType {
+    fn from(memory_type: MemoryType) -> Self {
+        match memory_type {
+            MemoryType::CONVENTIONAL
+            | MemoryType::BOOT_SERVICES_CODE
+            | MemoryType::BOOT_SERVICES_DATA => Self::Available,
+            MemoryType::LOADER_CODE | MemoryType::LOADER_DATA => Self::Loader,
+            MemoryType::ACPI_RECLAIM => Self::AcpiReclaimable,
+            MemoryType::ACPI_NON_VOLATILE => Self::AcpiNvs,
+            _ => Self::Reserved,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryDescriptor {
+    pub phys_start: u64,
+    /// The number of `UEFI_PAGE_SIZE` pages
+    pub page_count: u64,
+    pub ty: MemoryRegionType,
+}
+
+impl MemoryDescriptor {
+    const fn null() -> Self {
+        Self {
+            phys_start: 0,
+            page_count: 0,
+            ty: MemoryRegionType::Reserved,
+        }
+    }
+
+    pub fn phys_end(&self) -> u64 {
+        self.phys_start + self.page_count * UEFI_PAGE_SIZE as u64
+    }
+}
+
+/// A copy of the UEFI memory map which doesn't refer to any memory of the firmware.
+/// Descriptors are sorted by address.
+#[derive(Clone)]
+pub struct MemoryMap {
+    descriptors: [MemoryDescriptor; MAX_MEMORY_DESCRIPTORS],
+    len: usize,
+}
+
+impl MemoryMap {
+    /// Copies the memory map returned by `exit_boot_services`. Doesn't allocate.
+    /// If there are more than `MAX_MEMORY_DESCRIPTORS` descriptors after merging, the rest is dropped.
+    /// Dropped areas are treated as unavailable by the kernel, so this loses memory but is safe.
+    pub fn from_uefi(uefi_memory_map: &MemoryMapOwned) -> Self {
+        let mut memory_map = Self {
+            descriptors: [MemoryDescriptor::null(); MAX_MEMORY_DESCRIPTORS],
+            len: 0,
+        };
+
+        // The map returned by exit_boot_services is sorted by address.
+        for desc in uefi_memory_map.entries() {
+            memory_map.push(MemoryDescriptor {
+                phys_start: desc.phys_start,
+                page_count: desc.page_count,
+                ty: desc.ty.into(),
+            });
+        }
+
+        memory_map
+    }
+
+    fn push(&mut self, desc: MemoryDescriptor) {
+        if let Some(last) = self.descriptors[..self.len].last_mut() {
+            if last.ty == desc.ty && last.phys_end() == desc.phys_start {
+                last.page_count += desc.page_count;
+                return;
+            }
+        }
+
+        if self.len < MAX_MEMORY_DESCRIPTORS {
+            self.descriptors[self.len] = desc;
+            self.len += 1;
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &MemoryDescriptor> {
+        self.descriptors[..self.len].iter()
+    }
+}
@@ -0,0 +1,54 @@
+use crate::scan_code::ScanCodeSet;
+
+/// Messages from interrupt handlers to the main loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Message {
+    /// A scan code from the PS/2 keyboard, in the scan code set which the keyboard was switched to
+    KeyScanCode { code: u8, set: ScanCodeSet },
+    /// A byte received from COM1
+    SerialInput(u8),
+    /// Sent by the local APIC timer every second
+    TimerSecond,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: u8, set: ScanCodeSet) -> Message {
+        Message::KeyScanCode { code, set }
+    }
+
+    #[test]
+    fn key_scan_codes_are_equal_only_with_the_same_code_and_set() {
+        let esc = key(0x01, ScanCodeSet::Set1);
+        assert_eq!(esc, key(0x01, ScanCodeSet::Set1));
+        assert_ne!(esc, key(0x02, ScanCodeSet::Set1));
+        assert_ne!(esc, key(0x01, ScanCodeSet::Set2));
+    }
+
+    #[test]
+    fn serial_inputs_are_equal_only_with_the_same_byte() {
+        assert_eq!(Message::SerialInput(b'a'), Message::SerialInput(b'a'));
+        assert_ne!(Message::SerialInput(b'a'), Message::SerialInput(b'b'));
+    }
+
+    #[test]
+    fn timer_seconds_are_equal() {
+        assert_eq!(Message::TimerSecond, Message::TimerSecond);
+    }
+
+    #[test]
+    fn different_variants_are_not_equal() {
+        let messages = [
+            key(0x01, ScanCodeSet::Set1),
+            Message::SerialInput(0x01),
+            Message::TimerSecond,
+        ];
+        for (i, a) in messages.iter().enumerate() {
+            for (j, b) in messages.iter().enumerate() {
+                assert_eq!(a == b, i == j, "{:?} and {:?}", a, b);
+            }
+        }
+    }
+}
@@ -0,0 +1,33 @@
+// Scan code sets of the PS/2 keyboard.
+// The kernel's keyboard driver switches the keyboard to one of them, and the main loop decodes with it.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanCodeSet {
+    Set1 = 1,
+    Set2 = 2,
+    Set3 = 3,
+}
+
+impl ScanCodeSet {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Self::Set1),
+            2 => Some(Self::Set2),
+            3 => Some(Self::Set3),
+            _ => None,
+        }
+    }
+
+    pub fn as_u8(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the make code of the Esc key in this set.
+    pub fn escape_make_code(self) -> u8 {
+        match self {
+            Self::Set1 => 0x01,
+            Self::Set2 => 0x76,
+            Self::Set3 => 0x08,
+        }
+    }
+}
@@ -2,15 +2,21 @@ use core::{marker::PhantomData, ptr::read_unaligned};
 
 use acpi::{
     AcpiError,
+    address::AddressSpace,
     fadt::Fadt,
-    madt::{IoApicEntry, LocalApicEntry, Madt},
+    madt::{LocalApicEntry, Madt, MadtEntry},
     rsdp::Rsdp,
     sdt::SdtHeader,
 };
+use arrayvec::ArrayVec;
 use common::address::PhysPtr;
 use spin::{Mutex, Once};
+use x86_64::instructions::port::{Port, PortReadOnly};
 
-use crate::kprintln;
+use crate::{
+    arch::{Polarity, TriggerMode},
+    kprintln, qemu,
+};
 
 trait Validate {
     fn is_valid(&self) -> bool;
@@ -95,31 +101,107 @@ impl Xsdt {
     }
 }
 
+const IO_APIC_CAPACITY: usize = 8;
+const INTERRUPT_SOURCE_OVERRIDE_CAPACITY: usize = 16;
+
+#[derive(Debug, Clone, Copy)]
+pub struct IoApicInfo {
+    pub id: u8,
+    pub address: u32,
+    /// The first global system interrupt which this I/O APIC handles.
+    pub global_system_interrupt_base: u32,
+}
+
+/// An ISA IRQ which is connected to a different global system interrupt (GSI) than its number.
+#[derive(Debug, Clone, Copy)]
+struct InterruptSourceOverride {
+    irq: u8,
+    global_system_interrupt: u32,
+    polarity: Polarity,
+    trigger_mode: TriggerMode,
+}
+
+impl InterruptSourceOverride {
+    /// flags bit 0-1: Polarity (00 = conforms to the bus, 01 = active high, 11 = active low)
+    /// flags bit 2-3: Trigger Mode (00 = conforms to the bus, 01 = edge, 11 = level)
+    /// ISA interrupts are active high and edge-triggered.
+    fn new(irq: u8, global_system_interrupt: u32, flags: u16) -> Self {
+        let polarity = match flags & 0b11 {
+            0b11 => Polarity::ActiveLow,
+            _ => Polarity::ActiveHigh,
+        };
+        let trigger_mode = match (flags >> 2) & 0b11 {
+            0b11 => TriggerMode::Level,
+            _ => TriggerMode::Edge,
+        };
+
+        Self {
+            irq,
+            global_system_interrupt,
+            polarity,
+            trigger_mode,
+        }
+    }
+}
+
 pub struct ApicInfo {
     local_apic_base: u32,
     local_apic: LocalApicEntry,
-    io_apic: IoApicEntry,
+    io_apics: ArrayVec<IoApicInfo, IO_APIC_CAPACITY>,
+    interrupt_source_overrides: ArrayVec<InterruptSourceOverride, INTERRUPT_SOURCE_OVERRIDE_CAPACITY>,
 }
 
 impl ApicInfo {
     fn from_madt(madt: &Madt) -> Self {
-        // Find the entry about I/O APIC from the MADT.
-        let io_apic_entry = madt
-            .entries()
-            .find_map(|entry| {
-                if let acpi::madt::MadtEntry::IoApic(o) = entry {
-                    Some(o)
-                } else {
-                    None
+        // Collect the entries about I/O APICs and interrupt source overrides from the MADT.
+        let mut io_apics = ArrayVec::new();
+        let mut interrupt_source_overrides = ArrayVec::new();
+        for entry in madt.entries() {
+            match entry {
+                MadtEntry::IoApic(io_apic) => {
+                    let io_apic = IoApicInfo {
+                        id: io_apic.io_apic_id,
+                        address: io_apic.io_apic_address,
+                        global_system_interrupt_base: io_apic.global_system_interrupt_base,
+                    };
+                    if io_apics.try_push(io_apic).is_err() {
+                        kprintln!("Too many I/O APICs. I/O APIC {} is ignored.", io_apic.id);
+                    }
                 }
-            })
-            .expect("The entry about the I/O APIC wasn't found from the MADT");
+                MadtEntry::InterruptSourceOverride(entry) => {
+                    let interrupt_source_override = InterruptSourceOverride::new(
+                        entry.irq,
+                        entry.global_system_interrupt,
+                        entry.flags,
+                    );
+                    if interrupt_source_overrides
+                        .try_push(interrupt_source_override)
+                        .is_err()
+                    {
+                        kprintln!(
+                            "Too many interrupt source overrides. The override of IRQ {} is ignored.",
+                            interrupt_source_override.irq
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+        if io_apics.is_empty() {
+            panic!("The entry about the I/O APIC wasn't found from the MADT");
+        }
+        if io_apics.len() > 1 {
+            kprintln!(
+                "{} I/O APICs are found. Only the one which handles GSI 0 is programmed.",
+                io_apics.len()
+            );
+        }
 
         // Find the entry about Local APIC from the MADT.
         let local_apic_entry = madt
             .entries()
             .find_map(|entry| {
-                if let acpi::madt::MadtEntry::LocalApic(o) = entry {
+                if let MadtEntry::LocalApic(o) = entry {
                     Some(o)
                 } else {
                     None
@@ -133,7 +215,8 @@ impl ApicInfo {
         return Self {
             local_apic_base,
             local_apic: *local_apic_entry,
-            io_apic: *io_apic_entry,
+            io_apics,
+            interrupt_source_overrides,
         };
     }
 
@@ -142,14 +225,58 @@ impl ApicInfo {
         self.local_apic_base
     }
 
+    /// Returns the I/O APIC which handles GSI 0 (ISA IRQs), or the first one if there is no such I/O APIC.
+    fn primary_io_apic(&self) -> &IoApicInfo {
+        self.io_apics
+            .iter()
+            .find(|io_apic| io_apic.global_system_interrupt_base == 0)
+            .unwrap_or(&self.io_apics[0])
+    }
+
     #[inline]
     pub fn io_apic_base(&self) -> u32 {
-        self.io_apic.io_apic_address
+        self.primary_io_apic().address
     }
 
     #[inline]
     pub fn io_apic_id(&self) -> u8 {
-        self.io_apic.io_apic_id
+        self.primary_io_apic().id
+    }
+
+    #[inline]
+    pub fn io_apic_global_system_interrupt_base(&self) -> u32 {
+        self.primary_io_apic().global_system_interrupt_base
+    }
+
+    /// Returns the I/O APIC which handles `global_system_interrupt`.
+    /// Every I/O APIC is assumed to have 24 redirection entries since the MADT doesn't tell the number.
+    pub fn io_apic_for_gsi(&self, global_system_interrupt: u32) -> Option<&IoApicInfo> {
+        const ASSUMED_REDIRECTION_ENTRY_COUNT: u32 = 24;
+        self.io_apics
+            .iter()
+            .filter(|io_apic| io_apic.global_system_interrupt_base <= global_system_interrupt)
+            .filter(|io_apic| {
+                global_system_interrupt - io_apic.global_system_interrupt_base
+                    < ASSUMED_REDIRECTION_ENTRY_COUNT
+            })
+            .max_by_key(|io_apic| io_apic.global_system_interrupt_base)
+    }
+
+    /// Returns the global system interrupt which an ISA `irq` is connected to and its polarity and trigger mode.
+    /// Without an override, an ISA IRQ is identity-mapped, active high and edge-triggered.
+    pub fn gsi_for_irq(&self, irq: u8) -> (u32, Polarity, TriggerMode) {
+        match self
+            .interrupt_source_overrides
+            .iter()
+            .find(|interrupt_source_override| interrupt_source_override.irq == irq)
+        {
+            Some(interrupt_source_override) => (
+                interrupt_source_override.global_system_interrupt,
+                interrupt_source_override.polarity,
+                interrupt_source_override.trigger_mode,
+            ),
+            None => (irq as u32, Polarity::ActiveHigh, TriggerMode::Edge),
+        }
     }
 
     pub fn processor_id(&self) -> u8 {
@@ -157,6 +284,9 @@ impl ApicInfo {
     }
 }
 
+/// Frequency of the ACPI PM timer in Hz.
+pub const PM_TIMER_FREQ: u64 = 3579545;
+
 static FADT: Once<Fadt> = Once::new();
 static APIC_INFO: Once<ApicInfo> = Once::new();
 
@@ -216,3 +346,87 @@ pub fn get_apic_info() -> &'static ApicInfo {
         .get()
         .expect("acpi::get_apic_info is called before calling acpi::init.")
 }
+
+/// Returns the port of the PM timer and whether its counter is 32 bit (otherwise 24 bit).
+fn pm_timer() -> (PortReadOnly<u32>, bool) {
+    let fadt = get_fadt();
+    let pm_timer_block = fadt
+        .pm_timer_block()
+        .ok()
+        .flatten()
+        .expect("The FADT doesn't describe the PM timer.");
+    // Copy the flags out of the packed FADT before calling a method on them.
+    let flags = fadt.flags;
+
+    (
+        PortReadOnly::<u32>::new(pm_timer_block.address as u16),
+        flags.pm_timer_is_32_bit(),
+    )
+}
+
+/// The mask of the valid bits of the PM timer counter. The counter wraps around to 0 after this value.
+pub fn pm_timer_mask() -> u32 {
+    if pm_timer().1 { 0xffffffff } else { 0x00ffffff }
+}
+
+/// Reads the current count of the PM timer, which advances at PM_TIMER_FREQ Hz.
+pub fn read_pm_timer() -> u32 {
+    let (mut pm_timer, _) = pm_timer();
+    unsafe { pm_timer.read() }
+}
+
+/// Busy-waits for `msec` milliseconds using the ACPI PM timer.
+pub fn wait_milli_secs(msec: u64) {
+    let (mut pm_timer, pm_timer_is_32_bit) = pm_timer();
+    let read_count = |pm_timer: &mut PortReadOnly<u32>| unsafe { pm_timer.read() };
+
+    let start = read_count(&mut pm_timer);
+    let mut end = start.wrapping_add((PM_TIMER_FREQ * msec / 1000) as u32);
+    if !pm_timer_is_32_bit {
+        end &= 0x00ffffff;
+    }
+
+    // wait for the counter to wrap around first
+    if end < start {
+        while read_count(&mut pm_timer) >= start {}
+    }
+    while read_count(&mut pm_timer) < end {}
+}
+
+/// SLP_TYPa of the S5 (soft off) state. The real value is in the `\_S5` object of the DSDT, which needs an AML
+/// interpreter to read. QEMU (PIIX4 and ICH9) defines it as 0.
+const SLP_TYP_S5: u16 = 0;
+/// Bit 13 of PM1 Control: SLP_EN (the system enters the sleep state of SLP_TYP when 1 is written)
+const PM1_CONTROL_SLP_EN: u16 = 1 << 13;
+
+/// Turns off the machine by entering the S5 state through the PM1a control block of the FADT.
+/// Falls back to QEMU's isa-debug-exit device if the machine is still running after that
+/// (e.g. the firmware uses another SLP_TYP for S5), and halts forever if that doesn't work either.
+pub fn shutdown() -> ! {
+    x86_64::instructions::interrupts::disable();
+
+    match get_fadt().pm1a_control_block() {
+        Ok(block) if block.address_space == AddressSpace::SystemIo => {
+            let mut pm1a_control = Port::<u16>::new(block.address as u16);
+            // SLP_TYP is bit 10-12
+            unsafe { pm1a_control.write(SLP_TYP_S5 << 10 | PM1_CONTROL_SLP_EN) };
+            // the power goes off a little after the write
+            wait_milli_secs(100);
+            kprintln!("ACPI shutdown failed. It's safe to turn off the machine now.");
+        }
+        Ok(block) => kprintln!(
+            "The PM1a control block in {:?} is not supported. It's safe to turn off the machine now.",
+            block.address_space
+        ),
+        Err(err) => kprintln!(
+            "The FADT doesn't describe the PM1a control block: {:?}. It's safe to turn off the machine now.",
+            err
+        ),
+    }
+
+    qemu::exit_qemu();
+
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
@@ -1,6 +1,9 @@
 use core::arch::asm;
 use core::ptr::{read_volatile, write_volatile};
 
+use common::address::VirtPtr;
+pub use common::io_apic::{DeliveryMode, DestinationMode, Polarity, RedirectionEntry, TriggerMode};
+
 pub unsafe fn read_msr(msr: u32) -> u64 {
     let high: u32;
     let low: u32;
@@ -29,12 +32,15 @@ pub unsafe fn write_msr(msr: u32, value: u64) {
 // Actions to write are done while this struct is locked therefore, this struct is thread-safe (probably).
 #[derive(Debug, Clone, Copy)]
 pub struct LocalApic {
-    ptr: u32,
+    ptr: u64,
 }
 
 impl LocalApic {
-    pub const fn new(base_addr: u32) -> Self {
-        Self { ptr: base_addr }
+    /// base_addr: the virtual address where the local APIC registers are mapped
+    pub const fn new(base_addr: VirtPtr) -> Self {
+        Self {
+            ptr: base_addr.get(),
+        }
     }
 
     fn write(&self, offset: usize, value: u32) {
@@ -51,6 +57,11 @@ impl LocalApic {
         }
     }
 
+    /// Bit 24-31: Local APIC ID
+    pub fn read_id(&self) -> u8 {
+        (self.read(0x20 / 4) >> 24) as u8
+    }
+
     /// Volatile-write task priority register
     pub fn write_task_priority_register(&self, value: u32) {
         self.write(0x80 / 4, value);
@@ -130,9 +141,10 @@ struct IoApicMmioInterface {
 }
 
 impl IoApic {
-    pub const fn new(base_addr: u32) -> Self {
+    /// base_addr: the virtual address where the I/O APIC registers are mapped
+    pub fn new(base_addr: VirtPtr) -> Self {
         Self {
-            ptr: base_addr as *mut IoApicMmioInterface,
+            ptr: base_addr.mut_ptr(),
         }
     }
 
@@ -146,9 +158,9 @@ impl IoApic {
         write_volatile(&mut (*self.ptr).data, data);
     }
 
-    /// index: irq
-    /// value: vector
-    pub unsafe fn set_redirection_entry_at(&self, index: u32, value: u64) {
+    /// index: the input pin of the I/O APIC (= irq unless it is overridden)
+    pub unsafe fn set_redirection_entry_at(&self, index: u32, entry: RedirectionEntry) {
+        let value = entry.get();
         self.write(0x10 + 2 * index, value as u32);
         self.write(0x10 + 2 * index + 1, (value >> 32) as u32);
     }
@@ -0,0 +1,38 @@
+use core::arch::asm;
+
+use crate::kprintln;
+
+/// Stops walking a broken chain which never reaches the null frame pointer.
+const MAX_FRAME_COUNT: usize = 64;
+
+/// What `push rbp; mov rbp, rsp` in a function prologue leaves on the stack.
+/// The kernel is built with `-C force-frame-pointers=yes` so, every function has this prologue.
+#[repr(C)]
+struct StackFrame {
+    /// rbp of the caller
+    rbp: *const StackFrame,
+    return_address: u64,
+}
+
+/// Prints return addresses by following the saved rbp chain from the caller of this function.
+/// The chain ends with the null rbp set in `switch_to_kernel_stack`.
+pub fn print_backtrace() {
+    let mut rbp: *const StackFrame;
+    unsafe { asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags)) };
+
+    kprintln!("backtrace:");
+    for i in 0..MAX_FRAME_COUNT {
+        if rbp.is_null() || !rbp.is_aligned() {
+            break;
+        }
+
+        let frame = unsafe { rbp.read() };
+        if frame.return_address == 0 {
+            break;
+        }
+        // There is no symbol table in the kernel yet.
+        kprintln!("#{:<2} 0x{:016X}  ??", i, frame.return_address);
+
+        rbp = frame.rbp;
+    }
+}
@@ -0,0 +1,35 @@
+use common::boot::CmdLine;
+use spin::Once;
+
+// The command line passed by the bootloader from maizono.cfg.
+// Lines are `key=value`. When a key appears more than once, the last one is used.
+
+static CMDLINE: Once<CmdLine> = Once::new();
+
+/// Keeps a copy of the command line. BootInfo may not live forever.
+pub fn init(cmdline: &CmdLine) {
+    CMDLINE.call_once(|| *cmdline);
+}
+
+fn cmdline() -> &'static str {
+    CMDLINE
+        .get()
+        .expect("cmdline::cmdline is called before calling cmdline::init.")
+        .as_str()
+}
+
+pub fn get(key: &str) -> Option<&'static str> {
+    cmdline().lines().rev().find_map(|line| {
+        let (k, v) = line.split_once('=')?;
+        (k.trim() == key).then(|| v.trim())
+    })
+}
+
+/// Accepts `true`/`false`, `on`/`off`, `yes`/`no` and `1`/`0`.
+pub fn get_bool(key: &str) -> Option<bool> {
+    match get(key)? {
+        "true" | "on" | "yes" | "1" => Some(true),
+        "false" | "off" | "no" | "0" => Some(false),
+        _ => None,
+    }
+}
@@ -0,0 +1,138 @@
+use core::arch::{
+    asm,
+    x86_64::{__cpuid, CpuidResult},
+};
+use core::mem::{offset_of, MaybeUninit};
+use core::ptr::addr_of_mut;
+
+use crate::arch::{read_msr, write_msr};
+
+// references:
+// Intel 64 and IA-32 Architectures Software Developer's Manual Vol. 2A, CPUID
+
+fn cpuid(leaf: u32) -> CpuidResult {
+    __cpuid(leaf)
+}
+
+/// Returns the largest leaf which CPUID supports in the basic range (0 ~).
+fn max_basic_leaf() -> u32 {
+    cpuid(0x0).eax
+}
+
+/// Returns the largest leaf which CPUID supports in the extended range (0x80000000 ~).
+fn max_extended_leaf() -> u32 {
+    cpuid(0x8000_0000).eax
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuidReg {
+    Eax,
+    Ebx,
+    Ecx,
+    Edx,
+}
+
+/// Returns whether `bit` of `reg` is set in the result of CPUID `leaf` (sub-leaf 0).
+/// Unsupported leaves are treated as all bits clear.
+pub fn has_feature(leaf: u32, reg: CpuidReg, bit: u8) -> bool {
+    let max_leaf = if leaf >= 0x8000_0000 {
+        max_extended_leaf()
+    } else {
+        max_basic_leaf()
+    };
+    if leaf > max_leaf {
+        return false;
+    }
+
+    let result = cpuid(leaf);
+    let value = match reg {
+        CpuidReg::Eax => result.eax,
+        CpuidReg::Ebx => result.ebx,
+        CpuidReg::Ecx => result.ecx,
+        CpuidReg::Edx => result.edx,
+    };
+    value & (1 << bit) != 0
+}
+
+/// CPUID.01H:EDX bit 4: Time Stamp Counter
+pub fn has_tsc() -> bool {
+    has_feature(0x1, CpuidReg::Edx, 4)
+}
+
+/// CPUID.80000007H:EDX bit 8: the TSC runs at a constant rate in all ACPI P-, C- and T-states
+pub fn has_invariant_tsc() -> bool {
+    has_feature(0x8000_0007, CpuidReg::Edx, 8)
+}
+
+/// CPUID.80000001H:EDX bit 20: Execute Disable Bit (EFER.NXE can be set)
+pub fn has_execute_disable() -> bool {
+    has_feature(0x8000_0001, CpuidReg::Edx, 20)
+}
+
+/// CPUID.(EAX=07H, ECX=0H):EBX bit 7: Supervisor-Mode Execution Prevention
+pub fn has_smep() -> bool {
+    has_feature(0x7, CpuidReg::Ebx, 7)
+}
+
+/// CPUID.(EAX=07H, ECX=0H):EBX bit 20: Supervisor-Mode Access Prevention
+pub fn has_smap() -> bool {
+    has_feature(0x7, CpuidReg::Ebx, 20)
+}
+
+/// The maximum number of CPUs which have per-CPU data
+pub const MAX_CPU_COUNT: usize = 16;
+
+const IA32_GS_BASE: u32 = 0xc000_0101;
+const IA32_KERNEL_GS_BASE: u32 = 0xc000_0102;
+
+/// Data which each CPU has its own copy of. The GS base of a CPU points to its copy,
+/// so the fields are read with `gs:[offset]` without any lock.
+#[repr(C)]
+pub struct PerCpuData {
+    /// The address of this struct. `current` reads it to make a reference from GS.
+    self_ptr: u64,
+    pub apic_id: u8,
+}
+
+// Only written by `init_per_cpu_data`. After that, each CPU accesses its own entry through GS.
+static mut PER_CPU_DATA: [MaybeUninit<PerCpuData>; MAX_CPU_COUNT] =
+    [const { MaybeUninit::uninit() }; MAX_CPU_COUNT];
+
+/// Initializes the per-CPU data of the calling CPU and sets its GS base to it.
+/// `index` must be unique for each CPU (0 for the BSP).
+pub fn init_per_cpu_data(index: usize, apic_id: u8) {
+    assert!(index < MAX_CPU_COUNT, "cpu index {} exceeds MAX_CPU_COUNT.", index);
+
+    let data = unsafe { &mut *addr_of_mut!(PER_CPU_DATA[index]) };
+    let data = data.write(PerCpuData {
+        self_ptr: 0,
+        apic_id,
+    });
+    data.self_ptr = data as *mut PerCpuData as u64;
+    unsafe {
+        write_msr(IA32_GS_BASE, data.self_ptr);
+        // The same until user mode exists, so that a stray swapgs keeps GS valid.
+        write_msr(IA32_KERNEL_GS_BASE, data.self_ptr);
+    }
+}
+
+/// Returns the per-CPU data of the calling CPU. `init_per_cpu_data` must be called on this CPU before.
+pub fn current() -> &'static PerCpuData {
+    let ptr: u64;
+    unsafe {
+        asm!(
+            "mov {}, gs:[{}]",
+            out(reg) ptr,
+            const offset_of!(PerCpuData, self_ptr),
+            options(nostack, preserves_flags, readonly)
+        );
+        &*(ptr as *const PerCpuData)
+    }
+}
+
+/// Same as `current`, but returns None if `init_per_cpu_data` hasn't been called on this CPU yet.
+/// For paths which can run at any time, like the panic handler.
+pub fn try_current() -> Option<&'static PerCpuData> {
+    // The GS base is 0 until init_per_cpu_data sets it.
+    (unsafe { read_msr(IA32_GS_BASE) } != 0).then(current)
+}
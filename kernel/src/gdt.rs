@@ -2,13 +2,53 @@ use core::arch::asm;
 
 use spin::{Lazy, Mutex, MutexGuard};
 use x86_64::{
+    instructions::tables::load_tss,
     registers::segmentation::{Segment, CS, DS, ES, FS, GS, SS},
-    structures::gdt::SegmentSelector,
+    structures::{gdt::SegmentSelector, tss::TaskStateSegment},
     PrivilegeLevel::Ring0,
+    VirtAddr,
 };
 
-type Gdt = [SegmentDescriptor; 3];
-static GDT: Mutex<Gdt> = Mutex::new([SegmentDescriptor::new(); 3]);
+// The TSS descriptor is 16 bytes long so, it occupies two entries (3 and 4).
+type Gdt = [SegmentDescriptor; 5];
+static GDT: Mutex<Gdt> = Mutex::new([SegmentDescriptor::new(); 5]);
+
+const TSS_INDEX: u16 = 3;
+
+// Indexes of the interrupt stack table in the TSS.
+// Exceptions which can occur while the kernel stack is unusable switch to their own stack.
+pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+pub const MACHINE_CHECK_IST_INDEX: u16 = 1;
+pub const NON_MASKABLE_INTERRUPT_IST_INDEX: u16 = 2;
+
+const EMERGENCY_STACK_SIZE: usize = 1024 * 4 * 5;
+#[repr(align(16))]
+struct EmergencyStack([u8; EMERGENCY_STACK_SIZE]);
+impl EmergencyStack {
+    const fn new() -> Self {
+        Self([0; EMERGENCY_STACK_SIZE])
+    }
+}
+
+static mut DOUBLE_FAULT_STACK: EmergencyStack = EmergencyStack::new();
+static mut MACHINE_CHECK_STACK: EmergencyStack = EmergencyStack::new();
+static mut NON_MASKABLE_INTERRUPT_STACK: EmergencyStack = EmergencyStack::new();
+
+/// Returns the address of the end of the stack since stacks grow downwards.
+fn stack_end(stack: *const EmergencyStack) -> VirtAddr {
+    VirtAddr::from_ptr(stack) + EMERGENCY_STACK_SIZE as u64
+}
+
+static TSS: Lazy<TaskStateSegment> = Lazy::new(|| {
+    let mut tss = TaskStateSegment::new();
+    tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+        stack_end(&raw const DOUBLE_FAULT_STACK);
+    tss.interrupt_stack_table[MACHINE_CHECK_IST_INDEX as usize] =
+        stack_end(&raw const MACHINE_CHECK_STACK);
+    tss.interrupt_stack_table[NON_MASKABLE_INTERRUPT_IST_INDEX as usize] =
+        stack_end(&raw const NON_MASKABLE_INTERRUPT_STACK);
+    tss
+});
 
 pub fn init() {
     let mut gdt = GDT.lock();
@@ -16,6 +56,16 @@ pub fn init() {
     gdt[1].set_code_segment(DescriptorType::ExecuteRead, 0, 0, 0xfffff);
     gdt[2].set_data_segment(DescriptorType::ReadWrite, 0, 0, 0xfffff);
 
+    let tss_base = &*TSS as *const TaskStateSegment as u64;
+    let tss_limit = size_of::<TaskStateSegment>() as u32 - 1;
+    gdt[TSS_INDEX as usize].set_system_segment_low(
+        DescriptorType::TSSAvailable,
+        0,
+        tss_base,
+        tss_limit,
+    );
+    gdt[TSS_INDEX as usize + 1].set_system_segment_high(tss_base);
+
     unsafe { load_gdt(size_of::<Gdt>() as u16 - 1, gdt.as_ptr() as u64) };
 
     unsafe {
@@ -26,6 +76,8 @@ pub fn init() {
 
         CS::set_reg(SegmentSelector::new(1, Ring0));
         SS::set_reg(SegmentSelector::new(2, Ring0));
+
+        load_tss(SegmentSelector::new(TSS_INDEX, Ring0));
     }
 }
 
@@ -79,6 +131,37 @@ impl SegmentDescriptor {
         self.granularity(true);
     }
 
+    /// Sets the lower 8 bytes of a 16-byte system segment descriptor such as the TSS descriptor.
+    #[inline]
+    fn set_system_segment_low(
+        &mut self,
+        descriptor_type: DescriptorType,
+        descriptor_privilege_level: u8,
+        base: u64,
+        limit: u32,
+    ) {
+        self.0 = 0;
+
+        self.set_base_low((base & 0xffff) as u16);
+        self.set_base_middle(((base >> 16) & 0xff) as u8);
+        self.set_base_high(((base >> 24) & 0xff) as u8);
+
+        self.set_limit_low((limit & 0xffff) as u16);
+        self.set_limit_high(((limit >> 16) & 0xf) as u8);
+
+        self.set_descriptor_type(descriptor_type);
+        // the S flag is cleared for system segments
+        self.set_system_segment(false);
+        self.set_descriptor_privilege_level(descriptor_privilege_level);
+        self.set_present(true);
+    }
+
+    /// Sets the upper 8 bytes of a 16-byte system segment descriptor, which hold bits 32-63 of the base.
+    #[inline]
+    fn set_system_segment_high(&mut self, base: u64) {
+        self.0 = base >> 32;
+    }
+
     #[allow(dead_code)]
     #[inline]
     fn set_limit_low(&mut self, value: u16) {
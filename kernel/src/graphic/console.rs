@@ -1,24 +1,29 @@
 use core::{
-    ascii,
     fmt::{self},
     str,
+    sync::atomic::{AtomicU64, Ordering},
 };
 
 use common::graphic::RgbColor;
 use spin::{Mutex, MutexGuard};
 use thiserror_no_std::Error;
 
-use crate::error::Result;
+use crate::{error::Result, timer::precise::Stopwatch};
 
 use super::{
-    font::{self, CHARACTER_HEIGHT, CHARACTER_WIDTH},
+    font::{self, BoxDrawing, CHARACTER_HEIGHT, CHARACTER_WIDTH},
     frame_buffer,
 };
 
-const ROWS: usize = 25;
-const COLUMNS: usize = 150;
+/// The console is sized to fill the frame buffer, bounded by these.
+/// 2560x1600 with the 8x16 font
+const MAX_ROWS: usize = 100;
+const MAX_COLUMNS: usize = 320;
 
 static CONSOLE: Mutex<Console> = Mutex::new(Console::new_empty());
+/// How long the last scroll took in microseconds. Scrolling redraws every line, so it is the slowest drawing.
+/// 0 until the precise timer is calibrated.
+static LAST_SCROLL_US: AtomicU64 = AtomicU64::new(0);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
 pub enum ConsoleError {
@@ -28,16 +33,21 @@ pub enum ConsoleError {
     ConsoleLockError,
     #[error("The number of characters in the line overflowed the capacity.")]
     LineLengthOverflow,
+    #[error("The frame buffer is too small to show even one character.")]
+    FrameBufferTooSmall,
+    #[error("The area is outside of the console.")]
+    OutsideConsole,
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct Line<const CAP: usize> {
-    chars: [ascii::Char; CAP],
+    /// Characters without a glyph are kept as they are and drawn as a garbled glyph.
+    chars: [char; CAP],
     length: usize,
 }
 
 impl<const CAP: usize> Line<CAP> {
-    pub fn new(chars: [ascii::Char; CAP], length: usize) -> Result<Self> {
+    pub fn new(chars: [char; CAP], length: usize) -> Result<Self> {
         if length > CAP {
             return Err(ConsoleError::LineLengthOverflow.into());
         }
@@ -47,12 +57,12 @@ impl<const CAP: usize> Line<CAP> {
 
     pub const fn null() -> Self {
         Self {
-            chars: [ascii::Char::from_u8(0).unwrap(); CAP],
+            chars: ['\0'; CAP],
             length: 0,
         }
     }
 
-    pub fn push(&mut self, char: ascii::Char) -> Result<()> {
+    pub fn push(&mut self, char: char) -> Result<()> {
         if self.length == CAP {
             return Err(ConsoleError::LineLengthOverflow.into());
         }
@@ -62,14 +72,59 @@ impl<const CAP: usize> Line<CAP> {
 
         Ok(())
     }
+
+    /// Puts `char` at `column`. The gap between the end of the line and `column` is filled with spaces.
+    pub fn set(&mut self, column: usize, char: char) -> Result<()> {
+        if column >= CAP {
+            return Err(ConsoleError::LineLengthOverflow.into());
+        }
+
+        while self.length < column {
+            self.push(' ')?;
+        }
+        if column == self.length {
+            self.push(char)
+        } else {
+            self.chars[column] = char;
+            Ok(())
+        }
+    }
+
+    pub fn truncate(&mut self, length: usize) {
+        self.length = self.length.min(length);
+    }
+}
+
+/// The maximum number of numeric parameters in an escape sequence which is understood
+const MAX_ESCAPE_PARAMS: usize = 2;
+
+/// State of the parser of ANSI escape sequences.
+/// references:
+/// https://en.wikipedia.org/wiki/ANSI_escape_code#CSI_(Control_Sequence_Introducer)_sequences
+#[derive(Debug, Clone, Copy)]
+enum EscapeState {
+    Normal,
+    /// ESC was read
+    Escape,
+    /// ESC [ was read. `count` is the number of parameters started so far.
+    Csi {
+        params: [u16; MAX_ESCAPE_PARAMS],
+        count: usize,
+    },
 }
 
 pub struct Console {
-    buffer: [Line<COLUMNS>; ROWS],
+    buffer: [Line<MAX_COLUMNS>; MAX_ROWS],
+    /// The number of rows of the scrolling region. The status line, if any, is the row just below it.
+    rows: usize,
+    columns: usize,
     bg_color: RgbColor,
     fg_color: RgbColor,
     cursor_row: usize,
     cursor_column: usize,
+    escape_state: EscapeState,
+    status_row: Option<usize>,
+    status: Line<MAX_COLUMNS>,
 }
 
 impl fmt::Write for Console {
@@ -82,81 +137,334 @@ impl fmt::Write for Console {
 impl Console {
     const fn new_empty() -> Self {
         Self {
-            buffer: [Line::<COLUMNS>::null(); ROWS],
+            buffer: [Line::<MAX_COLUMNS>::null(); MAX_ROWS],
+            rows: 0,
+            columns: 0,
             bg_color: RgbColor::rgb(0x28, 0x28, 0x28),
             fg_color: RgbColor::rgb(0x28, 0x28, 0x28),
             cursor_row: 0,
             cursor_column: 0,
+            escape_state: EscapeState::Normal,
+            status_row: None,
+            status: Line::<MAX_COLUMNS>::null(),
         }
     }
 
+    /// Initializes the console to fill the frame buffer. The frame buffer must be initialized before.
     pub fn init(&mut self, bg_color: RgbColor, fg_color: RgbColor) -> Result<()> {
+        let rows = (frame_buffer::height()? / CHARACTER_HEIGHT).min(MAX_ROWS);
+        let columns = (frame_buffer::width()? / CHARACTER_WIDTH).min(MAX_COLUMNS);
+        if rows == 0 || columns == 0 {
+            return Err(ConsoleError::FrameBufferTooSmall.into());
+        }
+
         frame_buffer::fill_rect(
             0,
             0,
-            COLUMNS * CHARACTER_WIDTH,
-            ROWS * CHARACTER_HEIGHT,
+            columns * CHARACTER_WIDTH,
+            rows * CHARACTER_HEIGHT,
             bg_color,
         )?;
         *self = Self {
-            buffer: [Line::<COLUMNS>::null(); ROWS],
+            buffer: [Line::<MAX_COLUMNS>::null(); MAX_ROWS],
+            rows,
+            columns,
             bg_color,
             fg_color,
             cursor_row: 0,
             cursor_column: 0,
+            escape_state: EscapeState::Normal,
+            status_row: None,
+            status: Line::<MAX_COLUMNS>::null(),
         };
         Ok(())
     }
 
-    fn new_line(&mut self) {
-        self.cursor_column = 0;
-        if self.cursor_row < ROWS - 1 {
-            self.cursor_row += 1;
+    /// Changes the colors and redraws the whole console with them.
+    pub fn set_colors(&mut self, bg_color: RgbColor, fg_color: RgbColor) -> Result<()> {
+        self.bg_color = bg_color;
+        self.fg_color = fg_color;
+        self.redraw()
+    }
+
+    fn redraw(&mut self) -> Result<()> {
+        frame_buffer::fill_rect(
+            0,
+            0,
+            self.columns * CHARACTER_WIDTH,
+            self.rows * CHARACTER_HEIGHT,
+            self.bg_color,
+        )?;
+        for (row, line) in self.buffer[0..self.rows].iter().enumerate() {
+            for (i, c) in line.chars[0..line.length].iter().enumerate() {
+                frame_buffer::write_char(
+                    CHARACTER_WIDTH * i,
+                    CHARACTER_HEIGHT * row,
+                    *c,
+                    self.fg_color,
+                )?;
+            }
+        }
+        self.draw_status()
+    }
+
+    /// Reserves the bottom row for a status line which doesn't scroll, or returns the row to the scrolling region.
+    pub fn set_status_line_enabled(&mut self, enabled: bool) -> Result<()> {
+        if enabled == self.status_row.is_some() {
+            return Ok(());
+        }
+
+        if enabled {
+            if self.rows < 2 {
+                return Err(ConsoleError::FrameBufferTooSmall.into());
+            }
+            if self.cursor_row == self.rows - 1 {
+                // keep the line under the cursor visible
+                self.scroll_up();
+                self.cursor_row -= 1;
+            }
+            self.rows -= 1;
+            self.buffer[self.rows] = Line::<MAX_COLUMNS>::null();
+            self.status_row = Some(self.rows);
+            self.draw_status()
         } else {
             frame_buffer::fill_rect(
                 0,
-                0,
-                COLUMNS * CHARACTER_WIDTH,
-                ROWS * CHARACTER_HEIGHT,
-                self.bg_color.into(),
-            )
-            .expect("Failed to fill up the console.");
-
-            for row in 0..ROWS - 1 {
-                self.buffer[row] = self.buffer[row + 1];
-
-                let line = self.buffer[row];
-                for (i, c) in line.chars[0..line.length].iter().enumerate() {
-                    frame_buffer::write_char(
-                        font::CHARACTER_WIDTH * i,
-                        font::CHARACTER_HEIGHT * row,
-                        *c,
-                        self.fg_color,
-                    )
-                    .unwrap();
-                }
+                self.rows * CHARACTER_HEIGHT,
+                self.columns * CHARACTER_WIDTH,
+                CHARACTER_HEIGHT,
+                self.bg_color,
+            )?;
+            self.rows += 1;
+            self.status_row = None;
+            self.status = Line::<MAX_COLUMNS>::null();
+            Ok(())
+        }
+    }
+
+    /// Replaces the text of the status line. Characters beyond the width of the console are dropped.
+    pub fn set_status(&mut self, s: &str) -> Result<()> {
+        self.status = Line::<MAX_COLUMNS>::null();
+        for c in s.chars().filter(|c| !c.is_control()).take(self.columns) {
+            self.status.push(c)?;
+        }
+        self.draw_status()
+    }
+
+    /// Draws the status line with the colors swapped so that it stands out from the scrolling region.
+    fn draw_status(&mut self) -> Result<()> {
+        let Some(row) = self.status_row else {
+            return Ok(());
+        };
+
+        frame_buffer::fill_rect(
+            0,
+            row * CHARACTER_HEIGHT,
+            self.columns * CHARACTER_WIDTH,
+            CHARACTER_HEIGHT,
+            self.fg_color,
+        )?;
+        for (i, c) in self.status.chars[0..self.status.length].iter().enumerate() {
+            frame_buffer::write_char(
+                CHARACTER_WIDTH * i,
+                CHARACTER_HEIGHT * row,
+                *c,
+                self.bg_color,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Draws a box whose top-left corner is at (`column`, `row`) with box-drawing glyphs in `color`.
+    /// The size is given in characters and includes the border, so both must be at least 2.
+    /// The box is drawn on the screen only and is overwritten when the console scrolls.
+    pub fn draw_box(
+        &mut self,
+        column: usize,
+        row: usize,
+        columns: usize,
+        rows: usize,
+        color: RgbColor,
+    ) -> Result<()> {
+        if columns < 2 || rows < 2 || column + columns > self.columns || row + rows > self.rows {
+            return Err(ConsoleError::OutsideConsole.into());
+        }
+
+        let right = column + columns - 1;
+        let bottom = row + rows - 1;
+        for y in row..=bottom {
+            for x in column..=right {
+                let glyph = match (x, y) {
+                    (x, y) if x == column && y == row => BoxDrawing::DownRight,
+                    (x, y) if x == right && y == row => BoxDrawing::DownLeft,
+                    (x, y) if x == column && y == bottom => BoxDrawing::UpRight,
+                    (x, y) if x == right && y == bottom => BoxDrawing::UpLeft,
+                    (_, y) if y == row || y == bottom => BoxDrawing::Horizontal,
+                    (x, _) if x == column || x == right => BoxDrawing::Vertical,
+                    _ => continue,
+                };
+                frame_buffer::write_char(
+                    CHARACTER_WIDTH * x,
+                    CHARACTER_HEIGHT * y,
+                    glyph.as_char(),
+                    color,
+                )?;
             }
+        }
+        Ok(())
+    }
+
+    fn new_line(&mut self) {
+        if self.rows == 0 {
+            // not initialized yet
+            return;
+        }
 
-            *self.buffer.last_mut().expect("console buffer is empty") = Line::<COLUMNS>::null();
+        self.cursor_column = 0;
+        if self.cursor_row + 1 < self.rows {
+            self.cursor_row += 1;
+        } else {
+            self.scroll_up();
         }
     }
 
-    fn print(&mut self, s: &str) {
-        for c in s.as_ascii().expect("Non ascii character is given.") {
-            if *c == ascii::Char::LineFeed {
-                self.new_line()
-            } else if self.cursor_column < COLUMNS - 1 {
+    /// Moves every line of the scrolling region up by one and clears the last line.
+    fn scroll_up(&mut self) {
+        let stopwatch = Stopwatch::try_start();
+        frame_buffer::fill_rect(
+            0,
+            0,
+            self.columns * CHARACTER_WIDTH,
+            self.rows * CHARACTER_HEIGHT,
+            self.bg_color,
+        )
+        .expect("Failed to fill up the console.");
+
+        for row in 0..self.rows - 1 {
+            self.buffer[row] = self.buffer[row + 1];
+
+            let line = self.buffer[row];
+            for (i, c) in line.chars[0..line.length].iter().enumerate() {
                 frame_buffer::write_char(
-                    font::CHARACTER_WIDTH * self.cursor_column,
-                    font::CHARACTER_HEIGHT * self.cursor_row,
+                    font::CHARACTER_WIDTH * i,
+                    font::CHARACTER_HEIGHT * row,
                     *c,
                     self.fg_color,
                 )
                 .unwrap();
-                self.buffer[self.cursor_row].push(*c).unwrap();
-                self.cursor_column += 1;
             }
         }
+
+        self.buffer[self.rows - 1] = Line::<MAX_COLUMNS>::null();
+        if let Some(stopwatch) = stopwatch {
+            LAST_SCROLL_US.store(stopwatch.elapsed_us(), Ordering::Relaxed);
+        }
+    }
+
+    fn print(&mut self, s: &str) {
+        for c in s.chars() {
+            match self.escape_state {
+                EscapeState::Normal => self.print_char(c),
+                EscapeState::Escape => {
+                    self.escape_state = if c == '[' {
+                        EscapeState::Csi {
+                            params: [0; MAX_ESCAPE_PARAMS],
+                            count: 0,
+                        }
+                    } else {
+                        // unsupported sequence
+                        EscapeState::Normal
+                    };
+                }
+                EscapeState::Csi { mut params, count } => match c {
+                    '0'..='9' => {
+                        let count = count.max(1);
+                        if count <= MAX_ESCAPE_PARAMS {
+                            let param = &mut params[count - 1];
+                            *param = param
+                                .saturating_mul(10)
+                                .saturating_add(c as u16 - '0' as u16);
+                        }
+                        self.escape_state = EscapeState::Csi { params, count };
+                    }
+                    ';' => {
+                        self.escape_state = EscapeState::Csi {
+                            params,
+                            count: count.max(1) + 1,
+                        };
+                    }
+                    _ => {
+                        self.escape_state = EscapeState::Normal;
+                        self.execute_csi(c, &params[..count.min(MAX_ESCAPE_PARAMS)]);
+                    }
+                },
+            }
+        }
+    }
+
+    fn print_char(&mut self, c: char) {
+        if c == '\n' {
+            self.new_line()
+        } else if c == '\x1b' {
+            self.escape_state = EscapeState::Escape;
+        } else if self.cursor_column + 1 < self.columns {
+            let x = font::CHARACTER_WIDTH * self.cursor_column;
+            let y = font::CHARACTER_HEIGHT * self.cursor_row;
+            if self.cursor_column < self.buffer[self.cursor_row].length {
+                // overwriting a character moved to by an escape sequence
+                frame_buffer::fill_rect(x, y, CHARACTER_WIDTH, CHARACTER_HEIGHT, self.bg_color)
+                    .unwrap();
+            }
+            frame_buffer::write_char(x, y, c, self.fg_color).unwrap();
+            self.buffer[self.cursor_row]
+                .set(self.cursor_column, c)
+                .unwrap();
+            self.cursor_column += 1;
+        }
+    }
+
+    /// Executes the CSI sequence ending with `command`.
+    /// ESC[row;colH moves the cursor (1-based), ESC[2J clears the screen and ESC[K clears to the end of the line.
+    /// Others (including colors) are ignored.
+    fn execute_csi(&mut self, command: char, params: &[u16]) {
+        let param = |i: usize, default: u16| match params.get(i) {
+            Some(0) | None => default,
+            Some(value) => *value,
+        };
+
+        match command {
+            'H' | 'f' => {
+                let row = param(0, 1) as usize - 1;
+                let column = param(1, 1) as usize - 1;
+                self.cursor_row = row.min(self.rows.saturating_sub(1));
+                self.cursor_column = column.min(self.columns.saturating_sub(2));
+            }
+            'J' if params.first() == Some(&2) => {
+                for line in self.buffer[..self.rows].iter_mut() {
+                    *line = Line::<MAX_COLUMNS>::null();
+                }
+                frame_buffer::fill_rect(
+                    0,
+                    0,
+                    self.columns * CHARACTER_WIDTH,
+                    self.rows * CHARACTER_HEIGHT,
+                    self.bg_color,
+                )
+                .unwrap();
+            }
+            'K' if params.first().is_none_or(|mode| *mode == 0) => {
+                self.buffer[self.cursor_row].truncate(self.cursor_column);
+                frame_buffer::fill_rect(
+                    self.cursor_column * CHARACTER_WIDTH,
+                    self.cursor_row * CHARACTER_HEIGHT,
+                    (self.columns - self.cursor_column) * CHARACTER_WIDTH,
+                    CHARACTER_HEIGHT,
+                    self.bg_color,
+                )
+                .unwrap();
+            }
+            _ => {}
+        }
     }
 
     fn println(&mut self, s: &str) {
@@ -172,6 +480,15 @@ pub fn console() -> Result<MutexGuard<'static, Console>> {
     }
 }
 
+/// Releases the console lock regardless of who holds it.
+///
+/// # Safety
+/// Only for the panic handler. The holder of the lock may have left the console in the middle of an update,
+/// and it must never run again after this is called.
+pub unsafe fn force_unlock() {
+    unsafe { CONSOLE.force_unlock() };
+}
+
 #[macro_export]
 macro_rules! kprintln {
     ($($arg:tt)*) => {{
@@ -194,6 +511,28 @@ pub fn print(s: &str) -> Result<()> {
     Ok(())
 }
 
+pub fn draw_box(
+    column: usize,
+    row: usize,
+    columns: usize,
+    rows: usize,
+    color: RgbColor,
+) -> Result<()> {
+    console()?.draw_box(column, row, columns, rows, color)
+}
+
+pub fn set_status_line_enabled(enabled: bool) -> Result<()> {
+    console()?.set_status_line_enabled(enabled)
+}
+
+pub fn set_status(s: &str) -> Result<()> {
+    console()?.set_status(s)
+}
+
+pub fn last_scroll_us() -> u64 {
+    LAST_SCROLL_US.load(Ordering::Relaxed)
+}
+
 pub fn println(s: &str) -> Result<()> {
     console()?.println(s);
     Ok(())
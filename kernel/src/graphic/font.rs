@@ -5,6 +5,60 @@ pub const CHARACTER_HEIGHT: usize = 16;
 
 pub const GARBLED_FONT: [u8; CHARACTER_HEIGHT] = U8_FONT[0];
 
+/// Returns the glyph of `c`. `U8_FONT` covers Latin-1 (U+0000-U+00FF) and the box-drawing
+/// characters in `BoxDrawing`. Other characters are shown as `GARBLED_FONT`.
+pub fn glyph(c: char) -> &'static [u8; CHARACTER_HEIGHT] {
+    if let Some(box_drawing) = BoxDrawing::from_char(c) {
+        return &U8_FONT[box_drawing as usize];
+    }
+    match u8::try_from(c) {
+        Ok(index) => &U8_FONT[index as usize],
+        Err(_) => &GARBLED_FONT,
+    }
+}
+
+/// Box-drawing glyphs in `U8_FONT`. They are placed in 0x0e-0x18 (control characters).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BoxDrawing {
+    UpLeft = 0x0e,         // ┘
+    UpRight = 0x0f,        // └
+    DownRight = 0x10,      // ┌
+    DownLeft = 0x11,       // ┐
+    Horizontal = 0x12,     // ─
+    Vertical = 0x13,       // │
+    VerticalLeft = 0x14,   // ┤
+    UpHorizontal = 0x15,   // ┴
+    VerticalRight = 0x16,  // ├
+    DownHorizontal = 0x17, // ┬
+    Cross = 0x18,          // ┼
+}
+
+impl BoxDrawing {
+    /// The character which shows this glyph with `U8_FONT`.
+    pub const fn as_char(self) -> char {
+        self as u8 as char
+    }
+
+    /// Maps a Unicode box-drawing character (U+2500 block, light lines) to its glyph.
+    pub const fn from_char(c: char) -> Option<Self> {
+        match c {
+            '┘' => Some(Self::UpLeft),
+            '└' => Some(Self::UpRight),
+            '┌' => Some(Self::DownRight),
+            '┐' => Some(Self::DownLeft),
+            '─' => Some(Self::Horizontal),
+            '│' => Some(Self::Vertical),
+            '┤' => Some(Self::VerticalLeft),
+            '┴' => Some(Self::UpHorizontal),
+            '├' => Some(Self::VerticalRight),
+            '┬' => Some(Self::DownHorizontal),
+            '┼' => Some(Self::Cross),
+            _ => None,
+        }
+    }
+}
+
 pub const U8_FONT: [[u8; CHARACTER_HEIGHT]; 256] = [
     [
         213, 213, 0, 193, 0, 193, 193, 0, 193, 0, 193, 193, 0, 193, 0, 213,
@@ -1,11 +1,9 @@
-use core::ascii;
-
 use crate::error::Result;
-use common::graphic::{GraphicInfo, Pixel, PixelFormat, RgbColor};
+use common::graphic::{GraphicInfo, PixelFormat, RgbColor};
 use spin::{Mutex, MutexGuard};
 use thiserror_no_std::Error;
 
-use super::font::{self, GARBLED_FONT, U8_FONT};
+use super::font;
 
 static FRAME_BUF: Mutex<FrameBuf> = Mutex::new(FrameBuf::new());
 
@@ -21,6 +19,8 @@ pub enum FrameBufferError {
     FrameBufferLockError,
     #[error("Unsupported character.")]
     UnsupportedCharacterError,
+    #[error("The resolution and stride given by the bootloader don't fit in the frame buffer.")]
+    InvalidDimensionsError,
 }
 
 #[derive(Clone, Debug)]
@@ -28,11 +28,12 @@ pub struct FrameBuf {
     width: usize,
     height: usize,
     bytes_per_pixel: usize,
+    /// Pixels per scan line. Can be larger than `width` when scan lines are padded.
     stride: usize,
     pixel_format: PixelFormat,
     framebuf_addr: u64,
     framebuf_size: usize,
-    write_pixel: fn(&mut FrameBuf, usize, usize, Pixel) -> Result<()>,
+    write_pixel: fn(&mut FrameBuf, usize, usize, RgbColor) -> Result<()>,
 }
 
 impl FrameBuf {
@@ -50,6 +51,19 @@ impl FrameBuf {
     }
 
     pub fn init(&mut self, graphic_info: &GraphicInfo, bg_color: RgbColor) -> Result<()> {
+        // every pixel of every scan line must be inside the frame buffer
+        let required_size = graphic_info
+            .stride
+            .checked_mul(graphic_info.height)
+            .and_then(|pixels| pixels.checked_mul(graphic_info.bytes_per_pixel));
+        let is_valid = graphic_info.width > 0
+            && graphic_info.height > 0
+            && graphic_info.width <= graphic_info.stride
+            && required_size.is_some_and(|size| size <= graphic_info.size);
+        if !is_valid {
+            return Err(FrameBufferError::InvalidDimensionsError.into());
+        }
+
         *self = Self {
             width: graphic_info.width,
             height: graphic_info.height,
@@ -67,14 +81,14 @@ impl FrameBuf {
         Ok(())
     }
 
-    fn write_pixel(&mut self, x: usize, y: usize, pixel: Pixel) -> Result<()> {
-        (self.write_pixel)(self, x, y, pixel)
+    fn write_pixel(&mut self, x: usize, y: usize, color: RgbColor) -> Result<()> {
+        (self.write_pixel)(self, x, y, color)
     }
 
     fn fill(&mut self, color: RgbColor) -> Result<()> {
         for x in 0..self.width {
             for y in 0..self.height {
-                match self.write_pixel(x, y, color.into()) {
+                match self.write_pixel(x, y, color) {
                     Ok(_) => continue,
                     Err(err) => return Err(err),
                 }
@@ -93,7 +107,7 @@ impl FrameBuf {
     ) -> Result<()> {
         for x_inner in x..x + width {
             for y_inner in y..y + height {
-                self.write_pixel(x_inner, y_inner, color.into())?;
+                self.write_pixel(x_inner, y_inner, color)?;
             }
         }
         Ok(())
@@ -108,44 +122,56 @@ impl FrameBuf {
         color: RgbColor,
     ) -> Result<()> {
         for x_inner in x..x + width {
-            self.write_pixel(x_inner, y, color.into())?;
-            self.write_pixel(x_inner, y + height - 1, color.into())?;
+            self.write_pixel(x_inner, y, color)?;
+            self.write_pixel(x_inner, y + height - 1, color)?;
         }
         for y_inner in y..y + height {
-            self.write_pixel(x, y_inner, color.into())?;
-            self.write_pixel(x + width - 1, y_inner, color.into())?;
+            self.write_pixel(x, y_inner, color)?;
+            self.write_pixel(x + width - 1, y_inner, color)?;
         }
         Ok(())
     }
 
-    fn write_char(&mut self, x: usize, y: usize, ascii: ascii::Char, fg: RgbColor) -> Result<()> {
-        let glyph_index = ascii as usize;
-        let glyph = {
-            if glyph_index >= U8_FONT.len() {
-                GARBLED_FONT
-            } else {
-                U8_FONT[glyph_index]
-            }
-        };
+    /// Draws `c` at (x, y). Characters without a glyph are drawn as a garbled glyph.
+    fn write_char(&mut self, x: usize, y: usize, c: char, fg: RgbColor) -> Result<()> {
+        let glyph = font::glyph(c);
+
+        // Check the bounding box of the glyph once instead of checking every pixel.
+        if x + font::CHARACTER_WIDTH > self.width || y + font::CHARACTER_HEIGHT > self.height {
+            return Err(FrameBufferError::OutsideBufferError.into());
+        }
 
+        let value = self.pixel_value(fg);
         for (dy, row) in glyph.iter().enumerate() {
+            let row_ref = self.pixel_ptr(x, y + dy);
             for dx in 0..font::CHARACTER_WIDTH {
                 if (row >> 7 - dx) & 1 == 1 {
-                    self.write_pixel(x + dx, y + dy, fg.into())?;
+                    unsafe {
+                        *row_ref.add(dx) = value;
+                    }
                 }
             }
         }
         Ok(())
     }
 
+    /// Returns the value to store into the frame buffer to show `color`.
+    fn pixel_value(&self, color: RgbColor) -> u32 {
+        match self.pixel_format {
+            PixelFormat::Rgb => color.get_rgb_le(),
+            PixelFormat::Bgr => color.get_bgr_le(),
+        }
+    }
+
+    /// Returns the pointer to the pixel at (x, y). The caller must check (x, y) is inside the buffer.
+    fn pixel_ptr(&self, x: usize, y: usize) -> *mut u32 {
+        let offset = (y * self.stride + x) * self.bytes_per_pixel;
+        (self.framebuf_addr + offset as u64) as *mut u32
+    }
+
     fn write_string(&mut self, x: usize, y: usize, data: &str, fg: RgbColor) -> Result<()> {
-        for (i, c) in data
-            .as_ascii()
-            .expect("non ascii string is given.")
-            .iter()
-            .enumerate()
-        {
-            self.write_char(x + i * font::CHARACTER_WIDTH * 2, y, c.clone(), fg)?;
+        for (i, c) in data.chars().enumerate() {
+            self.write_char(x + i * font::CHARACTER_WIDTH * 2, y, c, fg)?;
         }
         Ok(())
     }
@@ -163,30 +189,29 @@ impl FrameBuf {
     }
 }
 
-fn write_pixel_rgb(self_: &mut FrameBuf, x: usize, y: usize, pixel: Pixel) -> Result<()> {
+fn write_pixel_rgb(self_: &mut FrameBuf, x: usize, y: usize, color: RgbColor) -> Result<()> {
     if !self_.is_inside_buffer(x, y) {
         return Err(FrameBufferError::OutsideBufferError.into());
     }
-    let offset = (y * self_.width + x) * self_.bytes_per_pixel;
+    let offset = (y * self_.stride + x) * self_.bytes_per_pixel;
     let pixel_ref = (self_.framebuf_addr + offset as u64) as *mut u32;
 
     unsafe {
-        *pixel_ref = pixel.le();
+        *pixel_ref = color.get_rgb_le();
     };
     Ok(())
 }
 
-fn write_pixel_bgr(self_: &mut FrameBuf, x: usize, y: usize, mut pixel: Pixel) -> Result<()> {
+fn write_pixel_bgr(self_: &mut FrameBuf, x: usize, y: usize, color: RgbColor) -> Result<()> {
     if !self_.is_inside_buffer(x, y) {
         return Err(FrameBufferError::OutsideBufferError.into());
     }
 
-    let offset = (y * self_.width + x) * self_.bytes_per_pixel;
+    let offset = (y * self_.stride + x) * self_.bytes_per_pixel;
     let pixel_ref = (self_.framebuf_addr + offset as u64) as *mut u32;
-    pixel.bgr();
 
     unsafe {
-        *pixel_ref = pixel.le();
+        *pixel_ref = color.get_bgr_le();
     };
     Ok(())
 }
@@ -197,12 +222,20 @@ pub fn frame_buf() -> Result<MutexGuard<'static, FrameBuf>> {
         .ok_or(FrameBufferError::FrameBufferLockError.into())
 }
 
-pub fn write_pixel(x: usize, y: usize, pixel: Pixel) -> Result<()> {
-    frame_buf()?.write_pixel(x, y, pixel)?;
+/// Releases the frame buffer lock regardless of who holds it.
+///
+/// # Safety
+/// Only for the panic handler. The holder of the lock must never run again after this is called.
+pub unsafe fn force_unlock() {
+    unsafe { FRAME_BUF.force_unlock() };
+}
+
+pub fn write_pixel(x: usize, y: usize, color: RgbColor) -> Result<()> {
+    frame_buf()?.write_pixel(x, y, color)?;
     Ok(())
 }
 
-pub fn write_char(x: usize, y: usize, c: ascii::Char, fg: RgbColor) -> Result<()> {
+pub fn write_char(x: usize, y: usize, c: char, fg: RgbColor) -> Result<()> {
     frame_buf()?.write_char(x, y, c, fg)?;
     Ok(())
 }
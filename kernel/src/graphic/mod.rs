@@ -3,3 +3,5 @@ pub mod console;
 pub mod font;
 pub mod frame_buffer;
 pub mod mouse;
+pub mod panic_banner;
+pub mod theme;
@@ -1,8 +1,6 @@
-use common::graphic::RgbColor;
-
 use crate::kprintln;
 
-use super::frame_buffer;
+use super::{frame_buffer, theme};
 
 pub const CURSOR_WIDTH: usize = 15;
 pub const CURSOR_HEIGHT: usize = 15;
@@ -31,6 +29,7 @@ const CURSOR_SHAPE_STR: [&str; 15] = [
 // }
 
 pub fn draw_cursor() {
+    let theme = theme::theme();
     for (y, row) in CURSOR_SHAPE_STR.into_iter().enumerate() {
         if y >= CURSOR_HEIGHT {
             kprintln!(
@@ -52,10 +51,10 @@ pub fn draw_cursor() {
             match c {
                 ' ' => continue,
                 '.' => {
-                    frame_buffer::write_pixel(x, y, RgbColor::from(0x689d6a00).into());
+                    frame_buffer::write_pixel(x, y, theme.mouse_cursor_fill);
                 }
                 '@' => {
-                    frame_buffer::write_pixel(x, y, RgbColor::from(0xfbf1c700).into());
+                    frame_buffer::write_pixel(x, y, theme.mouse_cursor_border);
                 }
                 other => {
                     kprintln!(
@@ -0,0 +1,83 @@
+use core::{fmt, panic::PanicInfo};
+
+use common::graphic::RgbColor;
+
+use super::{
+    console,
+    font::{CHARACTER_HEIGHT, CHARACTER_WIDTH},
+    frame_buffer,
+};
+
+const BANNER_BG: RgbColor = RgbColor::rgb(0xcc, 0x24, 0x1d);
+const BANNER_FG: RgbColor = RgbColor::rgb(0xff, 0xff, 0xff);
+/// The number of text rows in the banner. Longer messages are cut off.
+const BANNER_ROWS: usize = 4;
+
+/// Draws the panic message on a red banner at the top of the screen.
+/// The text is written to the frame buffer directly, so the console state doesn't matter.
+/// Only the border is drawn through the console and is left out if the console isn't usable.
+/// The frame buffer and the console locks must be available
+/// (the panic handler force-unlocks them before).
+pub fn draw(info: &PanicInfo) {
+    let Ok(width) = frame_buffer::width() else {
+        return;
+    };
+    let columns = width / CHARACTER_WIDTH;
+    // the border takes a row above and below the text
+    let height = (BANNER_ROWS + 2) * CHARACTER_HEIGHT;
+    if columns < 3 || frame_buffer::fill_rect(0, 0, width, height, BANNER_BG).is_err() {
+        return;
+    }
+    let _ = console::draw_box(0, 0, columns, BANNER_ROWS + 2, BANNER_FG);
+
+    let mut writer = BannerWriter {
+        // the border takes a column on each side
+        columns: columns - 2,
+        row: 0,
+        column: 0,
+    };
+    let _ = fmt::write(&mut writer, format_args!("KERNEL PANIC\n{}", info));
+}
+
+struct BannerWriter {
+    columns: usize,
+    row: usize,
+    column: usize,
+}
+
+impl BannerWriter {
+    fn new_line(&mut self) {
+        self.row += 1;
+        self.column = 0;
+    }
+}
+
+impl fmt::Write for BannerWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if self.row >= BANNER_ROWS {
+                return Ok(());
+            }
+            if c == '\n' {
+                self.new_line();
+                continue;
+            }
+            if self.column >= self.columns {
+                self.new_line();
+                if self.row >= BANNER_ROWS {
+                    return Ok(());
+                }
+            }
+
+            frame_buffer::write_char(
+                (self.column + 1) * CHARACTER_WIDTH,
+                (self.row + 1) * CHARACTER_HEIGHT,
+                c,
+                BANNER_FG,
+            )
+            .map_err(|_| fmt::Error)?;
+            self.column += 1;
+        }
+        Ok(())
+    }
+}
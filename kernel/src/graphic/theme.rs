@@ -0,0 +1,61 @@
+use common::graphic::RgbColor;
+use spin::Mutex;
+
+use crate::error::Result;
+
+use super::{console, frame_buffer};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub name: &'static str,
+    /// The color of the area which nothing is drawn on
+    pub background: RgbColor,
+    pub console_bg: RgbColor,
+    pub console_fg: RgbColor,
+    pub mouse_cursor_fill: RgbColor,
+    pub mouse_cursor_border: RgbColor,
+    pub accent: RgbColor,
+}
+
+/// https://github.com/morhetz/gruvbox
+pub const GRUVBOX_DARK: Theme = Theme {
+    name: "gruvbox-dark",
+    background: RgbColor::from_u32(0x282828),
+    console_bg: RgbColor::from_u32(0x3c3836),
+    console_fg: RgbColor::from_u32(0xebdbb2),
+    mouse_cursor_fill: RgbColor::from_u32(0x689d6a),
+    mouse_cursor_border: RgbColor::from_u32(0xfbf1c7),
+    accent: RgbColor::from_u32(0xd79921),
+};
+
+pub const GRUVBOX_LIGHT: Theme = Theme {
+    name: "gruvbox-light",
+    background: RgbColor::from_u32(0xfbf1c7),
+    console_bg: RgbColor::from_u32(0xebdbb2),
+    console_fg: RgbColor::from_u32(0x3c3836),
+    mouse_cursor_fill: RgbColor::from_u32(0x427b58),
+    mouse_cursor_border: RgbColor::from_u32(0x282828),
+    accent: RgbColor::from_u32(0xb57614),
+};
+
+pub const BUILT_IN_THEMES: [Theme; 2] = [GRUVBOX_DARK, GRUVBOX_LIGHT];
+
+static THEME: Mutex<Theme> = Mutex::new(GRUVBOX_DARK);
+
+/// Returns a copy of the active theme.
+pub fn theme() -> Theme {
+    *THEME.lock()
+}
+
+pub fn find_theme(name: &str) -> Option<Theme> {
+    BUILT_IN_THEMES.into_iter().find(|theme| theme.name == name)
+}
+
+/// Makes `theme` active and repaints the screen with it.
+pub fn set_theme(theme: Theme) -> Result<()> {
+    *THEME.lock() = theme;
+
+    frame_buffer::fill(theme.background)?;
+    console::console()?.set_colors(theme.console_bg, theme.console_fg)?;
+    Ok(())
+}
@@ -1,18 +1,28 @@
 use crate::{
     acpi,
-    arch::{self, IoApic, LocalApic, read_msr, write_msr},
-    ps2,
+    arch::{self, IoApic, LocalApic, RedirectionEntry, read_msr, write_msr},
+    gdt,
+    message::{self, Message},
+    paging::{self, MapKind},
+    ps2, serial, timer,
+};
+use common::address::{PhysPtr, VirtPtr};
+use core::{
+    ptr::{read_volatile, write_volatile},
+    sync::atomic::{AtomicU64, Ordering},
 };
-use common::address::PhysPtr;
-use core::ptr::{read_volatile, write_volatile};
 use spin::{Lazy, Mutex, MutexGuard, Once};
 use x86_64::instructions::port::Port;
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
 
 use crate::kprintln;
 
 static LOCAL_APIC: Once<LocalApic> = Once::new();
 
+/// The number of TaskWakeup IPIs this CPU has received.
+static TASK_WAKEUP_COUNT: AtomicU64 = AtomicU64::new(0);
+
 const EXTERNAL_IRQ_OFFSET: u8 = 32;
 
 #[derive(Debug, Clone, Copy)]
@@ -20,6 +30,7 @@ const EXTERNAL_IRQ_OFFSET: u8 = 32;
 enum IRQ {
     Timer = 0,
     Keyboard = 1, // PS/2 Keyboard
+    Serial = 4,   // COM1
     Mouse = 12,
     Error = 19, // Cpu internal error (LVT Error)
 }
@@ -34,9 +45,14 @@ impl IRQ {
 pub enum InterruptVector {
     EXTERNAL_IRQ_TIMER = EXTERNAL_IRQ_OFFSET + IRQ::Timer.as_u8(),
     EXTERNAL_IRQ_KEYBOARD = EXTERNAL_IRQ_OFFSET + IRQ::Keyboard.as_u8(),
+    EXTERNAL_IRQ_SERIAL = EXTERNAL_IRQ_OFFSET + IRQ::Serial.as_u8(),
     EXTERNAL_IRQ_MOUSE = EXTERNAL_IRQ_OFFSET + IRQ::Mouse.as_u8(),
     EXTERNAL_IRQ_ERROR = EXTERNAL_IRQ_OFFSET + IRQ::Error.as_u8(),
+    /// MSI of the xHC
+    Xhci = 0x40,
     LocalAPICTimer = 0x41,
+    /// Sent by another CPU to make this CPU reschedule
+    TaskWakeup = 0x50,
 }
 
 impl InterruptVector {
@@ -47,12 +63,55 @@ impl InterruptVector {
 
 static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
     let mut idt = InterruptDescriptorTable::new();
+
+    // CPU architectural exceptions
+    idt.divide_error.set_handler_fn(divide_error_handler);
+    idt.debug.set_handler_fn(debug_handler);
+    unsafe {
+        idt.non_maskable_interrupt
+            .set_handler_fn(non_maskable_interrupt_handler)
+            .set_stack_index(gdt::NON_MASKABLE_INTERRUPT_IST_INDEX);
+    }
     idt.breakpoint.set_handler_fn(breakpoint_handler);
-    idt.double_fault.set_handler_fn(double_fault_handler);
+    idt.overflow.set_handler_fn(overflow_handler);
+    idt.bound_range_exceeded
+        .set_handler_fn(bound_range_exceeded_handler);
+    idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+    idt.device_not_available
+        .set_handler_fn(device_not_available_handler);
+    unsafe {
+        idt.double_fault
+            .set_handler_fn(double_fault_handler)
+            .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+    }
+    idt.invalid_tss.set_handler_fn(invalid_tss_handler);
+    idt.segment_not_present
+        .set_handler_fn(segment_not_present_handler);
+    idt.stack_segment_fault
+        .set_handler_fn(stack_segment_fault_handler);
+    idt.general_protection_fault
+        .set_handler_fn(general_protection_fault_handler);
+    idt.page_fault.set_handler_fn(page_fault_handler);
+    idt.x87_floating_point
+        .set_handler_fn(x87_floating_point_handler);
+    idt.alignment_check.set_handler_fn(alignment_check_handler);
+    unsafe {
+        idt.machine_check
+            .set_handler_fn(machine_check_handler)
+            .set_stack_index(gdt::MACHINE_CHECK_IST_INDEX);
+    }
+    idt.simd_floating_point
+        .set_handler_fn(simd_floating_point_handler);
+    idt.virtualization.set_handler_fn(virtualization_handler);
+
+    // external interrupts
     idt[InterruptVector::LocalAPICTimer as u8].set_handler_fn(timer_interrupt_handler);
+    idt[InterruptVector::TaskWakeup as u8].set_handler_fn(task_wakeup_interrupt_handler);
+    idt[InterruptVector::Xhci as u8].set_handler_fn(xhci_interrupt_handler);
     idt[InterruptVector::EXTERNAL_IRQ_TIMER.as_u8()].set_handler_fn(timer_interrupt_handler);
     idt[InterruptVector::EXTERNAL_IRQ_KEYBOARD.as_u8()].set_handler_fn(keyboard_interrupt_handler);
     idt[InterruptVector::EXTERNAL_IRQ_MOUSE.as_u8()].set_handler_fn(mouse_interrupt_handler);
+    idt[InterruptVector::EXTERNAL_IRQ_SERIAL.as_u8()].set_handler_fn(serial_interrupt_handler);
 
     idt
 });
@@ -63,10 +122,80 @@ pub fn init() {
     init_apic();
 }
 
+pub fn get_local_apic() -> &'static LocalApic {
+    LOCAL_APIC
+        .get()
+        .expect("interrupts::get_local_apic is called before calling interrupts::init.")
+}
+
 fn init_idt() {
     IDT.load();
 }
 
+/// A gate descriptor read back from the loaded IDT.
+#[derive(Debug, Clone, Copy)]
+pub struct IdtGate {
+    pub vector: u8,
+    pub handler_address: u64,
+    pub selector: u16,
+    /// 0 means the handler doesn't switch stacks, otherwise the IST index + 1
+    pub ist: u8,
+    pub present: bool,
+}
+
+/// Reads the gate of `vector` from the IDT which is currently loaded in IDTR.
+pub fn read_idt_gate(vector: u8) -> Option<IdtGate> {
+    // references:
+    // Intel SDM Vol.3A 6.14.1 64-Bit Mode IDT
+    // Byte 0-1: Offset 15:0
+    // Byte 2-3: Segment Selector
+    // Byte 4-5: Bit 0-2: IST, Bit 8-11: Type, Bit 13-14: DPL, Bit 15: Present
+    // Byte 6-7: Offset 31:16
+    // Byte 8-11: Offset 63:32
+    const GATE_SIZE: usize = 16;
+
+    let idtr = x86_64::instructions::tables::sidt();
+    let offset = vector as usize * GATE_SIZE;
+    if offset + GATE_SIZE > idtr.limit as usize + 1 {
+        return None;
+    }
+
+    let raw = unsafe { read_volatile((idtr.base.as_u64() as usize + offset) as *const [u16; 8]) };
+    let options = raw[2];
+    let handler_address =
+        raw[0] as u64 | (raw[3] as u64) << 16 | (raw[4] as u64) << 32 | (raw[5] as u64) << 48;
+
+    Some(IdtGate {
+        vector,
+        handler_address,
+        selector: raw[1],
+        ist: (options & 0b111) as u8,
+        present: options & (1 << 15) != 0,
+    })
+}
+
+/// Prints every installed gate of the loaded IDT.
+pub fn dump_idt() {
+    let mut installed = 0;
+    for vector in 0..=u8::MAX {
+        let Some(gate) = read_idt_gate(vector) else {
+            break;
+        };
+        if !gate.present {
+            continue;
+        }
+        installed += 1;
+        kprintln!(
+            "vector 0x{:02X}: handler 0x{:016X}, selector 0x{:04X}, ist {}",
+            gate.vector,
+            gate.handler_address,
+            gate.selector,
+            gate.ist
+        );
+    }
+    kprintln!("{} / 256 vectors have a handler.", installed);
+}
+
 fn init_apic() {
     // Enabling APIC
     {
@@ -83,7 +212,7 @@ fn init_apic() {
     // Initializing Local APIC
     {
         const MASKED: u32 = 1 << 16;
-        let local_apic = LocalApic::new(acpi::get_apic_info().local_apic_base());
+        let local_apic = LocalApic::new(map_apic(acpi::get_apic_info().local_apic_base()));
         LOCAL_APIC.call_once(|| local_apic);
 
         // https://github.com/mit-pdos/xv6-public/blob/master/lapic.c
@@ -131,7 +260,7 @@ fn init_apic() {
             "I/O APIC base: 0x{:X}",
             acpi::get_apic_info().io_apic_base()
         );
-        let io_apic = IoApic::new(acpi::get_apic_info().io_apic_base());
+        let io_apic = IoApic::new(map_apic(acpi::get_apic_info().io_apic_base()));
 
         // https://github.com/mit-pdos/xv6-public/blob/master/ioapic.c
 
@@ -141,27 +270,89 @@ fn init_apic() {
 
         // Mark all interrupts edge-triggered, active high, disabled, and not routed to any CPUs.
         for i in 0..io_apic.get_max_amount_of_redirection_entries() {
-            // Bit 16 Interrupt mask. Stops the interrupt from reaching the processor if set.
-            let value = (EXTERNAL_IRQ_OFFSET as u64 + i as u64) | (1 << 16);
+            let entry = RedirectionEntry::new(EXTERNAL_IRQ_OFFSET + i as u8).masked(true);
             unsafe {
-                io_apic.set_redirection_entry_at(i as u32, value);
+                io_apic.set_redirection_entry_at(i as u32, entry);
             }
         }
 
         // Redirect external interrupts to IDT via I/O Apic.
-        let cpu0 = (acpi::get_apic_info().processor_id() as u64) << (32 + 24);
-        unsafe {
-            io_apic.set_redirection_entry_at(
-                IRQ::Keyboard as u32,
-                InterruptVector::EXTERNAL_IRQ_KEYBOARD as u64 | cpu0,
-            );
-
-            io_apic.set_redirection_entry_at(
-                IRQ::Mouse as u32,
-                InterruptVector::EXTERNAL_IRQ_MOUSE as u64 | cpu0,
-            );
+        // The destination is the local APIC ID of this CPU, which can differ from the ACPI processor id.
+        let bsp_local_apic_id = get_local_apic().read_id();
+        route_irq(&io_apic, IRQ::Keyboard, InterruptVector::EXTERNAL_IRQ_KEYBOARD, bsp_local_apic_id);
+        route_irq(&io_apic, IRQ::Mouse, InterruptVector::EXTERNAL_IRQ_MOUSE, bsp_local_apic_id);
+        route_irq(&io_apic, IRQ::Serial, InterruptVector::EXTERNAL_IRQ_SERIAL, bsp_local_apic_id);
+    }
+}
+
+/// Sends the fixed interrupt `vector` to the CPU whose local APIC ID is `target_apic_id`.
+pub fn send_ipi(target_apic_id: u8, vector: u8) {
+    // Interrupt Command Register
+    // High bit 24-31 (bit 56-63 of ICR): Destination
+    // Low bit 0-7: Vector, bit 8-10: Delivery Mode (000 = fixed), bit 11: Destination Mode (0 = physical),
+    // bit 12: Delivery Status, bit 14: Level (1 = assert), bit 15: Trigger Mode (0 = edge)
+    const DELIVERY_STATUS: u32 = 1 << 12;
+    const LEVEL_ASSERT: u32 = 1 << 14;
+
+    let local_apic = get_local_apic();
+    // An interrupt handler which sends an IPI between the two writes would send ours to its destination.
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        while local_apic.read_interrupt_command_register_low() & DELIVERY_STATUS != 0 {}
+        local_apic.write_interrupt_command_register_high((target_apic_id as u32) << 24);
+        // writing the low half sends the IPI
+        local_apic.write_interrupt_command_register_low(vector as u32 | LEVEL_ASSERT);
+    });
+}
+
+/// Sends a TaskWakeup IPI to this CPU and returns whether its handler ran within `timeout_ms`.
+/// Interrupts must be enabled.
+pub fn self_ipi_test(timeout_ms: u64) -> bool {
+    let before = TASK_WAKEUP_COUNT.load(Ordering::Relaxed);
+    send_ipi(get_local_apic().read_id(), InterruptVector::TaskWakeup.as_u8());
+    for _ in 0..timeout_ms {
+        if TASK_WAKEUP_COUNT.load(Ordering::Relaxed) != before {
+            return true;
         }
+        acpi::wait_milli_secs(1);
     }
+    TASK_WAKEUP_COUNT.load(Ordering::Relaxed) != before
+}
+
+/// Returns the virtual address of the APIC registers at `base`.
+fn map_apic(base: u32) -> VirtPtr {
+    // Both the local APIC and I/O APIC registers fit in a 4 KiB page.
+    const APIC_MMIO_SIZE: usize = 0x1000;
+
+    let mut phys = PhysPtr::null();
+    phys.set(base as u64);
+    paging::map_physical(phys, APIC_MMIO_SIZE, MapKind::Mmio)
+        .expect("Failed to map the APIC registers.")
+}
+
+/// Programs the redirection entry for an ISA `irq`, following the interrupt source overrides in the MADT.
+fn route_irq(io_apic: &IoApic, irq: IRQ, vector: InterruptVector, destination: u8) {
+    let apic_info = acpi::get_apic_info();
+    let (global_system_interrupt, polarity, trigger_mode) = apic_info.gsi_for_irq(irq.as_u8());
+
+    // Only the primary I/O APIC is programmed.
+    let is_on_primary_io_apic = apic_info
+        .io_apic_for_gsi(global_system_interrupt)
+        .is_some_and(|io_apic_info| io_apic_info.id == apic_info.io_apic_id());
+    if !is_on_primary_io_apic {
+        kprintln!(
+            "IRQ {:?} (GSI {}) isn't handled by the primary I/O APIC. It is not routed.",
+            irq,
+            global_system_interrupt
+        );
+        return;
+    }
+
+    let index = global_system_interrupt - apic_info.io_apic_global_system_interrupt_base();
+    let entry = RedirectionEntry::new(vector.as_u8())
+        .polarity(polarity)
+        .trigger_mode(trigger_mode)
+        .destination(destination);
+    unsafe { io_apic.set_redirection_entry_at(index, entry) };
 }
 
 unsafe fn disable_pic_8259() {
@@ -172,8 +363,12 @@ unsafe fn disable_pic_8259() {
 }
 
 extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    let code = unsafe { ps2::controller().keyboard().read_data() };
-    kprintln!("pressed");
+    if let Ok(code) = unsafe { ps2::controller().keyboard().read_data() } {
+        message::enqueue_from_interrupt(Message::KeyScanCode {
+            code,
+            set: ps2::scan_code_set(),
+        });
+    }
     LOCAL_APIC.wait().write_end_of_interrupt_register(0);
 }
 
@@ -185,20 +380,233 @@ extern "x86-interrupt" fn mouse_interrupt_handler(_stack_frame: InterruptStackFr
     LOCAL_APIC.wait().write_end_of_interrupt_register(0);
 }
 
+extern "x86-interrupt" fn task_wakeup_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    // There is no scheduler yet. Receiving the interrupt is enough to wake the CPU from hlt.
+    TASK_WAKEUP_COUNT.fetch_add(1, Ordering::Relaxed);
+    LOCAL_APIC.wait().write_end_of_interrupt_register(0);
+}
+
+extern "x86-interrupt" fn xhci_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    // There is no xHCI driver yet, so the controller is never started and this isn't expected to run.
+    LOCAL_APIC.wait().write_end_of_interrupt_register(0);
+}
+
+extern "x86-interrupt" fn serial_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    serial::on_interrupt();
+    LOCAL_APIC.wait().write_end_of_interrupt_register(0);
+}
+
+/// Prints a line of an exception report. An exception can be taken while the interrupted code holds
+/// the console lock, so the report goes to serial without any lock first, and to the console only if it is free.
+macro_rules! exception_println {
+    ($($arg:tt)*) => {{
+        crate::serial_emergency_println!($($arg)*);
+        if let Ok(mut console) = crate::graphic::console::console() {
+            use core::fmt::Write;
+            let _ = writeln!(console, $($arg)*);
+        }
+    }};
+}
+
+fn print_stack_frame(stack_frame: &InterruptStackFrame) {
+    exception_println!("RIP: 0x{:016X}", stack_frame.instruction_pointer.as_u64());
+    exception_println!("CS: {:?}", stack_frame.code_segment);
+    exception_println!("RFLAGS: 0x{:016X}", stack_frame.cpu_flags.bits());
+    exception_println!("RSP: 0x{:016X}", stack_frame.stack_pointer.as_u64());
+    exception_println!("SS: {:?}", stack_frame.stack_segment);
+}
+
+/// Prints an error code which refers to a segment selector.
+/// https://wiki.osdev.org/Exceptions#Selector_Error_Code
+fn print_selector_error_code(error_code: u64) {
+    if error_code == 0 {
+        exception_println!("error code: 0 (not segment related)");
+        return;
+    }
+
+    let external = error_code & 0b1 != 0;
+    let table = match (error_code >> 1) & 0b11 {
+        0b00 => "GDT",
+        0b10 => "LDT",
+        _ => "IDT",
+    };
+    let index = (error_code >> 3) & 0x1fff;
+    exception_println!(
+        "error code: 0x{:X} (table: {}, index: {}, external: {})",
+        error_code,
+        table,
+        index,
+        external
+    );
+}
+
+enum ErrorCode {
+    /// An error code which refers to a segment selector.
+    Selector(u64),
+    PageFault(PageFaultErrorCode),
+    Raw(u64),
+}
+
+/// Prints the state at an exception which the kernel can't recover from and halts.
+fn handle_fatal_exception(
+    name: &str,
+    vector: u8,
+    error_code: Option<ErrorCode>,
+    stack_frame: &InterruptStackFrame,
+) -> ! {
+    exception_println!("EXCEPTION: {} (vector {})", name, vector);
+    match error_code {
+        Some(ErrorCode::Selector(error_code)) => print_selector_error_code(error_code),
+        Some(ErrorCode::PageFault(error_code)) => {
+            exception_println!("error code: 0x{:X} ({:?})", error_code.bits(), error_code);
+            // CR2 holds the address which caused the page fault.
+            let accessed = Cr2::read_raw();
+            exception_println!("accessed address: 0x{:016X}", accessed);
+            match paging::Translator::new().virt_to_phys(VirtPtr::new(accessed)) {
+                Some(phys) => exception_println!("which is mapped to 0x{:016X}", phys.get()),
+                None => exception_println!("which isn't mapped"),
+            }
+        }
+        Some(ErrorCode::Raw(error_code)) => exception_println!("error code: 0x{:X}", error_code),
+        None => {}
+    }
+    print_stack_frame(stack_frame);
+    panic!("{} occurred.", name);
+}
+
+extern "x86-interrupt" fn divide_error_handler(stack_frame: InterruptStackFrame) {
+    handle_fatal_exception("divide error (#DE)", 0, None, &stack_frame);
+}
+
+extern "x86-interrupt" fn debug_handler(stack_frame: InterruptStackFrame) {
+    // A hardware breakpoint or a single step. The interrupted code can continue.
+    exception_println!(
+        "EXCEPTION: debug (#DB) at RIP 0x{:016X}",
+        stack_frame.instruction_pointer.as_u64()
+    );
+}
+
+extern "x86-interrupt" fn non_maskable_interrupt_handler(stack_frame: InterruptStackFrame) {
+    handle_fatal_exception("non-maskable interrupt (NMI)", 2, None, &stack_frame);
+}
+
+extern "x86-interrupt" fn overflow_handler(stack_frame: InterruptStackFrame) {
+    handle_fatal_exception("overflow (#OF)", 4, None, &stack_frame);
+}
+
+extern "x86-interrupt" fn bound_range_exceeded_handler(stack_frame: InterruptStackFrame) {
+    handle_fatal_exception("bound range exceeded (#BR)", 5, None, &stack_frame);
+}
+
+extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: InterruptStackFrame) {
+    handle_fatal_exception("invalid opcode (#UD)", 6, None, &stack_frame);
+}
+
+extern "x86-interrupt" fn device_not_available_handler(stack_frame: InterruptStackFrame) {
+    handle_fatal_exception("device not available (#NM)", 7, None, &stack_frame);
+}
+
+extern "x86-interrupt" fn invalid_tss_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    handle_fatal_exception(
+        "invalid TSS (#TS)",
+        10,
+        Some(ErrorCode::Selector(error_code)),
+        &stack_frame,
+    );
+}
+
+extern "x86-interrupt" fn segment_not_present_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    handle_fatal_exception(
+        "segment not present (#NP)",
+        11,
+        Some(ErrorCode::Selector(error_code)),
+        &stack_frame,
+    );
+}
+
+extern "x86-interrupt" fn stack_segment_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    handle_fatal_exception(
+        "stack segment fault (#SS)",
+        12,
+        Some(ErrorCode::Selector(error_code)),
+        &stack_frame,
+    );
+}
+
+extern "x86-interrupt" fn general_protection_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) {
+    handle_fatal_exception(
+        "general protection fault (#GP)",
+        13,
+        Some(ErrorCode::Selector(error_code)),
+        &stack_frame,
+    );
+}
+
+extern "x86-interrupt" fn page_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    handle_fatal_exception(
+        "page fault (#PF)",
+        14,
+        Some(ErrorCode::PageFault(error_code)),
+        &stack_frame,
+    );
+}
+
+extern "x86-interrupt" fn x87_floating_point_handler(stack_frame: InterruptStackFrame) {
+    handle_fatal_exception("x87 floating-point exception (#MF)", 16, None, &stack_frame);
+}
+
+extern "x86-interrupt" fn alignment_check_handler(stack_frame: InterruptStackFrame, error_code: u64) {
+    handle_fatal_exception(
+        "alignment check (#AC)",
+        17,
+        Some(ErrorCode::Raw(error_code)),
+        &stack_frame,
+    );
+}
+
+extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
+    handle_fatal_exception("machine check (#MC)", 18, None, &stack_frame);
+}
+
+extern "x86-interrupt" fn simd_floating_point_handler(stack_frame: InterruptStackFrame) {
+    handle_fatal_exception("SIMD floating-point exception (#XM)", 19, None, &stack_frame);
+}
+
+extern "x86-interrupt" fn virtualization_handler(stack_frame: InterruptStackFrame) {
+    handle_fatal_exception("virtualization exception (#VE)", 20, None, &stack_frame);
+}
+
 extern "x86-interrupt" fn breakpoint_handler(_stack_frame: InterruptStackFrame) {
     // kprintln!("{:#?}", stack_frame);
-    kprintln!("breakpoint exception occured.");
+    exception_println!("breakpoint exception occured.");
     LOCAL_APIC.wait().write_end_of_interrupt_register(0);
 }
 
 extern "x86-interrupt" fn double_fault_handler(
-    _stack_frame: InterruptStackFrame,
-    _error_code: u64,
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
 ) -> ! {
-    panic!("double fault occurred.");
+    handle_fatal_exception(
+        "double fault (#DF)",
+        8,
+        Some(ErrorCode::Raw(error_code)),
+        &stack_frame,
+    );
 }
 
 extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    // timer::local_apic_timer_on_interrupt();
+    timer::local_apic_timer_on_interrupt();
     LOCAL_APIC.wait().write_end_of_interrupt_register(0);
 }
@@ -0,0 +1,93 @@
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+use crate::{cmdline, kprintln, serial_println, timer};
+
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+static LOGGER: KernelLogger = KernelLogger;
+/// The maximum level which is emitted. Stored as `LevelFilter as usize`.
+static LEVEL: AtomicUsize = AtomicUsize::new(DEFAULT_LEVEL as usize);
+/// Whether records are also printed on the console. Serial is always used.
+static CONSOLE_ENABLED: AtomicBool = AtomicBool::new(true);
+
+struct KernelLogger;
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let timestamp = Timestamp(timer::precise::rdtsc_ns());
+        serial_println!("{}[{}] {}", timestamp, record.level(), record.args());
+        if CONSOLE_ENABLED.load(Ordering::Relaxed) {
+            kprintln!("{}[{}] {}", timestamp, record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Prints a time in nanoseconds as `[seconds.microseconds] `, or nothing if there is no time
+/// (before the TSC is calibrated or when it isn't used).
+struct Timestamp(Option<u64>);
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(ns) => write!(f, "[{:5}.{:06}] ", ns / 1_000_000_000, ns % 1_000_000_000 / 1000),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Installs the logger. `loglevel=<0-5>` (off, error, warn, info, debug, trace), or the name of the level,
+/// and `console_log=<bool>` in the command line override the defaults.
+pub fn init() {
+    match cmdline::get("loglevel").map(parse_level) {
+        Some(Some(level)) => set_level(level),
+        Some(None) => kprintln!("loglevel is invalid. {} is used.", level()),
+        None => {}
+    }
+    if let Some(enabled) = cmdline::get_bool("console_log") {
+        set_console_enabled(enabled);
+    }
+
+    log::set_logger(&LOGGER).expect("logger::init is called twice.");
+    // The filtering is done by the logger so that the level can be changed at any time.
+    log::set_max_level(LevelFilter::Trace);
+}
+
+/// Accepts `0`-`5` or a level name like `debug`.
+fn parse_level(value: &str) -> Option<LevelFilter> {
+    match value.parse::<usize>() {
+        Ok(level) => LevelFilter::iter().nth(level),
+        Err(_) => value.parse().ok(),
+    }
+}
+
+pub fn level() -> LevelFilter {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+pub fn set_level(level: LevelFilter) {
+    LEVEL.store(level as usize, Ordering::Relaxed);
+}
+
+pub fn set_console_enabled(enabled: bool) {
+    CONSOLE_ENABLED.store(enabled, Ordering::Relaxed);
+}
@@ -11,23 +11,37 @@
 
 mod acpi;
 mod arch;
+mod backtrace;
+mod cmdline;
+mod cpu;
 mod error;
 mod gdt;
 mod graphic;
 mod interrupts;
+mod logger;
 mod memory_map;
+mod message;
 mod paging;
 mod pci;
 mod phys_mem_manager;
 mod ps2;
+mod qemu;
+mod rtc;
+mod serial;
+mod timer;
 
+use core::fmt::Write;
 use core::panic::PanicInfo;
 use core::{arch::asm, ptr::read_unaligned};
 
-use common::{boot::BootInfo, graphic::RgbColor};
+use arrayvec::ArrayString;
+
+use common::boot::BootInfo;
+use message::Message;
 use graphic::{
     console,
     frame_buffer::{self},
+    theme,
 };
 
 const KERNEL_STACK_SIZE: usize = 1024 * 1024;
@@ -57,6 +71,8 @@ fn switch_to_kernel_stack(
         asm!(
             "mov rdi, {}",
             "mov rsp, {}",
+            // terminates the frame pointer chain for backtraces
+            "xor rbp, rbp",
             "call {}",
             in(reg) boot_info, in(reg) KERNEL_STACK.as_ptr() as u64 + KERNEL_STACK.len() as u64,
             in(reg) new_entry
@@ -74,20 +90,52 @@ pub extern "sysv64" fn _start(boot_info: &BootInfo) -> ! {
 }
 
 fn main(boot_info: &BootInfo) -> ! {
+    cmdline::init(&boot_info.cmdline);
+
+    let theme = theme::theme();
     frame_buffer::frame_buf()
         .unwrap()
-        .init(&boot_info.graphic_info, RgbColor::from(0x28282800))
+        .init(&boot_info.graphic_info, theme.background)
         .unwrap();
     console::console()
         .unwrap()
-        .init(RgbColor::from(0x3c383600), RgbColor::from(0xebdbb200))
+        .init(theme.console_bg, theme.console_fg)
         .unwrap();
+    if let Some(name) = cmdline::get("theme") {
+        match theme::find_theme(name) {
+            Some(theme) => theme::set_theme(theme).unwrap(),
+            None => kprintln!("theme {} is not found. The default theme is used.", name),
+        }
+    }
     gdt::init();
+    serial::init().unwrap_or_else(|err| kprintln!("failed to initialize the serial port: {:?}", err));
+    logger::init();
+    // map_physical allocates page tables from the frame manager for MMIO outside of the window.
+    phys_mem_manager::init(&boot_info.memory_map);
+    {
+        let mem_manager = phys_mem_manager::mem_manager();
+        kprintln!(
+            "free frames: {} / {} ({:?})",
+            mem_manager.free_frame_count(),
+            mem_manager.total_frame_count(),
+            mem_manager.strategy()
+        );
+    }
     paging::init();
-    pci::devices()
-        .unwrap()
-        .init()
-        .unwrap_or_else(|err| kprintln!("{:#?}", err));
+    if cmdline::get_bool("paging_self_test").unwrap_or(false) {
+        match paging::map_mmio_self_test() {
+            Ok(true) => kprintln!("map_mmio self test: passed"),
+            Ok(false) => kprintln!("map_mmio self test: FAILED"),
+            Err(err) => kprintln!("map_mmio self test: {:?}", err),
+        }
+    }
+    match pci::devices() {
+        Ok(mut devices) => devices
+            .init()
+            .unwrap_or_else(|err| kprintln!("failed to scan PCI devices: {:?}", err)),
+        Err(err) => kprintln!("failed to lock PCI devices: {:?}", err),
+    }
+    pci::print_devices(true).unwrap_or_else(|err| kprintln!("{:#?}", err));
 
     let rsdp_addr = boot_info.rsdp_addr.unwrap_or_else(|| {
         kprintln!("RSDP adderss wan't found. The kernel will panic.");
@@ -96,25 +144,120 @@ fn main(boot_info: &BootInfo) -> ! {
     kprintln!("rsdp_addr: 0x{:X}", rsdp_addr.get());
 
     unsafe { acpi::init(rsdp_addr) };
-    // timer::init_local_apic_timer();
-    // timer::start_local_apic_timer();
 
     ps2::init();
     interrupts::init();
+    if cmdline::get_bool("dump_idt").unwrap_or(false) {
+        interrupts::dump_idt();
+    }
+    let bsp_apic_id = interrupts::get_local_apic().read_id();
+    cpu::init_per_cpu_data(0, bsp_apic_id);
+    match pci::configure_xhc_msi(bsp_apic_id, interrupts::InterruptVector::Xhci as u8) {
+        Ok(Some(xhc)) => kprintln!(
+            "xHC {:02x}:{:02x}.{} [{:04x}:{:04x}]: MSI is configured.",
+            xhc.get_bus(),
+            xhc.get_device(),
+            xhc.get_func(),
+            xhc.vendor_id(),
+            xhc.device_id()
+        ),
+        Ok(None) => {}
+        Err(err) => kprintln!("failed to configure MSI of the xHC: {:?}", err),
+    }
+    timer::init_local_apic_timer();
+    timer::precise::init();
     x86_64::instructions::interrupts::enable();
+    if cmdline::get_bool("ipi_self_test").unwrap_or(false) {
+        if interrupts::self_ipi_test(100) {
+            kprintln!("self IPI: received");
+        } else {
+            kprintln!("self IPI: not received within 100 ms");
+        }
+    }
+
+    kprintln!("date: {}", rtc::rtc().read().format());
 
-    phys_mem_manager::mem_manager().init(&boot_info.memory_map);
+    let status_line = cmdline::get_bool("status_line").unwrap_or(false);
+    if status_line {
+        console::set_status_line_enabled(true)
+            .unwrap_or_else(|err| kprintln!("failed to enable the status line: {:?}", err));
+        update_status_line();
+    }
+
+    let esc_shutdown = cmdline::get_bool("esc_shutdown").unwrap_or(false);
 
     kprintln!("It didn't crash.");
     loop {
-        unsafe { asm!("hlt") }
+        // Check the queue with interrupts disabled so that an interrupt between the check and hlt
+        // doesn't leave its data unprocessed until the next interrupt.
+        x86_64::instructions::interrupts::disable();
+        let Some(message) = message::dequeue() else {
+            x86_64::instructions::interrupts::enable_and_hlt();
+            continue;
+        };
+        x86_64::instructions::interrupts::enable();
+
+        match message {
+            Message::KeyScanCode { code, set }
+                if esc_shutdown && code == set.escape_make_code() =>
+            {
+                kprintln!("Esc is pressed. Shutting down.");
+                acpi::shutdown();
+            }
+            Message::KeyScanCode { code, set } => {
+                kprintln!("pressed: 0x{:02X} (scan code {:?})", code, set)
+            }
+            Message::SerialInput(byte) => serial_print!("{}", byte as char),
+            Message::TimerSecond if status_line => update_status_line(),
+            Message::TimerSecond => {}
+        }
     }
 }
 
+/// Shows the date, the uptime, the free memory and the time of the last console scroll on the status line.
+fn update_status_line() {
+    // a frame is 4 KiB
+    let free_mib = phys_mem_manager::mem_manager().free_frame_count() * 4 / 1024;
+    let mut status = ArrayString::<128>::new();
+    // a status which doesn't fit is just cut off
+    let _ = write!(
+        status,
+        " {} | up {}s | free {} MiB | scroll {} us",
+        rtc::rtc().read().format(),
+        timer::uptime_ms() / 1000,
+        free_mib,
+        console::last_scroll_us()
+    );
+    console::set_status(&status).unwrap_or_else(|err| serial_println!("{:?}", err));
+}
+
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    x86_64::instructions::interrupts::disable();
+
+    // Serial first: it works even if the graphics are broken.
+    serial_emergency_println!("[panic]");
+    serial_emergency_println!("{}", info);
+    if let Some(cpu) = cpu::try_current() {
+        serial_emergency_println!("on the CPU with APIC ID {}", cpu.apic_id);
+    }
+
+    // The panic may have happened while the console or the frame buffer was locked.
+    // Nothing else runs after this point, so the locks can be taken over.
+    unsafe {
+        frame_buffer::force_unlock();
+        console::force_unlock();
+    }
+    graphic::panic_banner::draw(info);
+
     kprintln!("[panic]");
     kprintln!("{}", info);
+    if let Some(cpu) = cpu::try_current() {
+        kprintln!("on the CPU with APIC ID {}", cpu.apic_id);
+    }
+    backtrace::print_backtrace();
+    // the console output may have scrolled over the banner
+    graphic::panic_banner::draw(info);
     loop {
         unsafe { asm!("hlt") }
     }
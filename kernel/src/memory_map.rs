@@ -1,10 +1,8 @@
-use uefi::boot::MemoryType;
+use common::memory_map::MemoryRegionType;
 
-pub const UEFI_PAGE_SIZE: usize = 1024 * 4;
+pub use common::memory_map::UEFI_PAGE_SIZE;
 
 #[inline]
-pub fn is_available(memory_type: MemoryType) -> bool {
-    memory_type == MemoryType::BOOT_SERVICES_CODE
-        || memory_type == MemoryType::BOOT_SERVICES_DATA
-        || memory_type == MemoryType::CONVENTIONAL
+pub fn is_available(memory_type: MemoryRegionType) -> bool {
+    memory_type == MemoryRegionType::Available
 }
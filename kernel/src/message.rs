@@ -0,0 +1,38 @@
+pub use common::message::Message;
+
+use common::arrayqueue::ArrayQueue;
+use thiserror_no_std::Error;
+
+use crate::serial_emergency_println;
+
+const MESSAGE_QUEUE_SIZE: usize = 256;
+
+/// Messages from interrupt handlers to the main loop.
+///
+/// The queue is single-producer single-consumer. All producers are interrupt handlers on the BSP,
+/// which don't nest (interrupt gates clear IF), so they act as one producer. The main loop is the consumer.
+static MESSAGE_QUEUE: ArrayQueue<Message, MESSAGE_QUEUE_SIZE> = ArrayQueue::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum MessageError {
+    #[error("The message queue is full.")]
+    QueueFull,
+}
+
+/// Posts `message` to the main loop. Only for interrupt handlers.
+pub fn enqueue(message: Message) -> Result<(), MessageError> {
+    MESSAGE_QUEUE.push(message).map_err(|_| MessageError::QueueFull)
+}
+
+/// `enqueue` which reports a full queue on serial and drops the message.
+/// The console lock may be held by the interrupted code, so serial is used.
+pub fn enqueue_from_interrupt(message: Message) {
+    if let Err(err) = enqueue(message) {
+        serial_emergency_println!("{} {:?} is dropped.", err, message);
+    }
+}
+
+/// Takes the oldest message. Only for the main loop.
+pub fn dequeue() -> Option<Message> {
+    MESSAGE_QUEUE.pop()
+}
@@ -1,9 +1,20 @@
 use core::arch::asm;
 use core::ops::{Deref, DerefMut};
+use core::ptr::{read_volatile, write_volatile};
 
+use common::address::{PHYS_WINDOW_SIZE, PHYS_WINDOW_START, PhysPtr, VirtPtr, phys_to_virt};
 use spin::Mutex;
+use thiserror_no_std::Error;
+use x86_64::instructions::tlb;
+use x86_64::registers::control::{Cr3, Cr4, Cr4Flags};
+use x86_64::registers::model_specific::{Efer, EferFlags};
+use x86_64::VirtAddr;
+
+use crate::{
+    cpu,
+    phys_mem_manager::{self, FrameID, MemoryManagerError},
+};
 
-#[allow(dead_code)]
 const PAGE_SIZE_4K: usize = 1024 * 4;
 const PAGE_SIZE_2M: usize = 1024 * 1024 * 2;
 const PAGE_SIZE_1G: usize = 1024 * 1024 * 1024 * 1;
@@ -99,6 +110,8 @@ pub fn init() {
 
     // unsafe {
     page_map_level4_table[0] = (&*page_dir_ptr_table).as_ptr() as u64 | 0x003;
+    // the physical memory window maps the same physical memory as the identity mapping
+    page_map_level4_table[PHYS_WINDOW_PML4_INDEX] = (&*page_dir_ptr_table).as_ptr() as u64 | 0x003;
     // PAGE_MAP_LEVEL4_TABLE[0] = PAGE_DIR_PTR_TABLE.as_ptr() as u64 | 0x003;
     // }
 
@@ -123,4 +136,244 @@ pub fn init() {
             in(reg) &*page_map_level4_table
         );
     }
+
+    enable_execute_disable();
+    enable_supervisor_protection();
+}
+
+/// Sets CR4.SMEP (bit 20) and CR4.SMAP (bit 21) if supported.
+/// With them, the kernel faults on executing or accessing user pages (unless EFLAGS.AC is set by stac).
+fn enable_supervisor_protection() {
+    let mut flags = Cr4Flags::empty();
+    if cpu::has_smep() {
+        flags |= Cr4Flags::SUPERVISOR_MODE_EXECUTION_PROTECTION;
+    }
+    if cpu::has_smap() {
+        flags |= Cr4Flags::SUPERVISOR_MODE_ACCESS_PREVENTION;
+    }
+    unsafe { Cr4::update(|cr4| cr4.insert(flags)) };
+}
+
+/// Sets EFER.NXE so that bit 63 of page table entries (XD) takes effect.
+/// Without it, the bit is reserved and setting it causes a page fault.
+fn enable_execute_disable() {
+    if !cpu::has_execute_disable() {
+        return;
+    }
+    unsafe { Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE)) };
+}
+
+/// Bit 0: Present
+const PAGE_PRESENT: u64 = 1 << 0;
+/// Bit 1: Read/Write (writable if set)
+const PAGE_WRITABLE: u64 = 1 << 1;
+/// Bit 3: Page-level Write-Through
+const PAGE_WRITE_THROUGH: u64 = 1 << 3;
+/// Bit 4: Page-level Cache Disable
+const PAGE_NO_CACHE: u64 = 1 << 4;
+/// Bit 7: Page Size (the entry maps a 1 GiB page in PDPT or a 2 MiB page in PD)
+const PAGE_HUGE: u64 = 1 << 7;
+/// Bit 12-51: Physical address of the next table or the page
+const PAGE_ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// Translates virtual addresses with the page tables which are active when this is created.
+pub struct Translator {
+    pml4_addr: u64,
+}
+
+impl Default for Translator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Translator {
+    /// Reads CR3. The page tables must not be switched while this is used.
+    pub fn new() -> Self {
+        let (frame, _) = Cr3::read();
+        Self {
+            pml4_addr: frame.start_address().as_u64(),
+        }
+    }
+
+    /// Walks the page tables and returns the physical address `virt` is mapped to.
+    /// Returns `None` if `virt` isn't mapped.
+    pub fn virt_to_phys(&self, virt: VirtPtr) -> Option<PhysPtr> {
+        let addr = virt.get();
+        let indices = [
+            (addr >> 39) & 0x1ff, // PML4
+            (addr >> 30) & 0x1ff, // PDPT
+            (addr >> 21) & 0x1ff, // PD
+            (addr >> 12) & 0x1ff, // PT
+        ];
+        // the size of the page mapped by an entry of each level
+        let page_sizes = [0, PAGE_SIZE_1G as u64, PAGE_SIZE_2M as u64, PAGE_SIZE_4K as u64];
+
+        let mut table_addr = self.pml4_addr;
+        for (level, index) in indices.iter().enumerate() {
+            let table = phys_to_virt(PhysPtr::new(table_addr)).ptr::<u64>();
+            let entry = unsafe { *table.add(*index as usize) };
+            if entry & PAGE_PRESENT == 0 {
+                return None;
+            }
+
+            let is_last_level = level == indices.len() - 1;
+            if is_last_level || (level != 0 && entry & PAGE_HUGE != 0) {
+                let page_size = page_sizes[level];
+                let page_addr = entry & PAGE_ADDRESS_MASK & !(page_size - 1);
+                let mut phys = PhysPtr::null();
+                phys.set(page_addr | (addr & (page_size - 1)));
+                return Some(phys);
+            }
+
+            table_addr = entry & PAGE_ADDRESS_MASK;
+        }
+
+        None
+    }
+}
+
+// The physical memory window shares the page directories of the identity mapping.
+const _: () = assert!(PHYS_WINDOW_SIZE == (NUMBER_OF_PAGE_DIR * PAGE_SIZE_1G) as u64);
+const PHYS_WINDOW_PML4_INDEX: usize = ((PHYS_WINDOW_START >> 39) & 0x1ff) as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapKind {
+    /// RAM (page tables, ACPI tables, ...). Accessed through the physical memory window.
+    Memory,
+    /// Memory mapped registers. Accessed through the physical memory window if it covers them
+    /// (the firmware sets MTRRs of MMIO regions there to uncacheable), otherwise mapped by `map_mmio`.
+    Mmio,
+}
+
+/// Returns the virtual address to access `len` bytes of physical memory from `phys`.
+/// `MapKind::Mmio` regions outside of the window need `phys_mem_manager` to be initialized.
+pub fn map_physical(phys: PhysPtr, len: usize, kind: MapKind) -> Result<VirtPtr, PagingError> {
+    let is_in_window = phys
+        .get()
+        .checked_add(len as u64)
+        .is_some_and(|end| end <= PHYS_WINDOW_SIZE);
+    if is_in_window {
+        return Ok(phys_to_virt(phys));
+    }
+
+    match kind {
+        MapKind::Memory => Err(PagingError::OutsidePhysicalWindow),
+        MapKind::Mmio => map_mmio(phys, len),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum PagingError {
+    #[error("The physical memory is outside of the physical memory window.")]
+    OutsidePhysicalWindow,
+    #[error("Failed to allocate a frame for a page table: {0}")]
+    FrameAllocationError(MemoryManagerError),
+    #[error("The virtual address range for MMIO is exhausted.")]
+    MmioRangeExhausted,
+}
+
+impl From<MemoryManagerError> for PagingError {
+    fn from(err: MemoryManagerError) -> Self {
+        Self::FrameAllocationError(err)
+    }
+}
+
+/// Virtual address range which `map_mmio` maps regions into (PML4 entry 402, 512 GiB)
+const MMIO_RANGE_START: u64 = 0xffff_c900_0000_0000;
+const MMIO_RANGE_END: u64 = MMIO_RANGE_START + 512 * PAGE_SIZE_1G as u64;
+
+/// The next virtual address `map_mmio` uses. Mapped ranges are never unmapped.
+static MMIO_NEXT: Mutex<u64> = Mutex::new(MMIO_RANGE_START);
+
+/// Maps `bytes` bytes of physical memory from `phys` into a fresh virtual range as uncached memory
+/// and returns the virtual address of `phys`. For MMIO regions which are outside of the identity mapping.
+/// Page tables are allocated from `phys_mem_manager`, so it must be initialized before.
+pub fn map_mmio(phys: PhysPtr, bytes: usize) -> Result<VirtPtr, PagingError> {
+    let page_offset = phys.get() % PAGE_SIZE_4K as u64;
+    let phys_start = phys.get() - page_offset;
+    let page_count = (page_offset as usize + bytes).div_ceil(PAGE_SIZE_4K).max(1);
+
+    let virt_start = {
+        let mut next = MMIO_NEXT.lock();
+        let start = *next;
+        let end = start + (page_count * PAGE_SIZE_4K) as u64;
+        if end > MMIO_RANGE_END {
+            return Err(PagingError::MmioRangeExhausted);
+        }
+        *next = end;
+        start
+    };
+
+    let mut pml4 = PAGE_MAP_LEVEL4_TABLE.lock();
+    for i in 0..page_count {
+        let virt = virt_start + (i * PAGE_SIZE_4K) as u64;
+        let entry = page_table_entry(&mut pml4, virt)?;
+        *entry = (phys_start + (i * PAGE_SIZE_4K) as u64)
+            | PAGE_PRESENT
+            | PAGE_WRITABLE
+            | PAGE_WRITE_THROUGH
+            | PAGE_NO_CACHE;
+        tlb::flush(VirtAddr::new(virt));
+    }
+
+    Ok(VirtPtr::new(virt_start + page_offset))
+}
+
+/// Returns the page table entry (level 1) which maps the 4 KiB page of `virt`.
+/// Missing tables on the way are allocated, and they must be inside the physical memory window.
+/// `virt` must not be in a huge page.
+fn page_table_entry(pml4: &mut PageMapLevel4Table, virt: u64) -> Result<&'static mut u64, PagingError> {
+    let mut table = pml4.as_mut_ptr();
+    // the PML4 is in the kernel image, which is identity mapped
+    let mut table_phys = table as u64;
+    // PML4, PDPT and PD
+    for shift in [39, 30, 21] {
+        let index = ((virt >> shift) & 0x1ff) as usize;
+        let entry = unsafe { &mut *table.add(index) };
+        if *entry & PAGE_PRESENT == 0 {
+            // Keep the tables of a walk together, next to the table which points to the new one.
+            let hint = FrameID::new(table_phys as usize / PAGE_SIZE_4K);
+            let frame = phys_mem_manager::mem_manager().alloc_near(hint)?;
+            let new_table = frame.start_address();
+            // The new table is accessed through the physical memory window, which doesn't cover all frames.
+            if new_table >= PHYS_WINDOW_SIZE {
+                phys_mem_manager::mem_manager().free(frame, 1)?;
+                return Err(PagingError::OutsidePhysicalWindow);
+            }
+            let new_table_ptr = phys_to_virt(PhysPtr::new(new_table)).mut_ptr::<u64>();
+            unsafe { core::ptr::write_bytes(new_table_ptr, 0, 512) };
+            *entry = new_table | PAGE_PRESENT | PAGE_WRITABLE;
+        }
+        table_phys = *entry & PAGE_ADDRESS_MASK;
+        table = phys_to_virt(PhysPtr::new(table_phys)).mut_ptr::<u64>();
+    }
+
+    let index = ((virt >> 12) & 0x1ff) as usize;
+    Ok(unsafe { &mut *table.add(index) })
+}
+
+/// Maps a free frame with `map_mmio`, writes a pattern through the mapping and reads it back.
+/// Also checks that `Translator` resolves the mapping to the frame. Returns whether both checks passed.
+/// The frame stays allocated because mappings made by `map_mmio` are never removed.
+pub fn map_mmio_self_test() -> Result<bool, PagingError> {
+    const PATTERN: u64 = 0x5a5a_a5a5_0123_4567;
+
+    let frame = phys_mem_manager::mem_manager().alloc(1)?;
+    let phys = PhysPtr::new(frame.start_address());
+    let virt = match map_mmio(phys, PAGE_SIZE_4K) {
+        Ok(virt) => virt,
+        Err(err) => {
+            phys_mem_manager::mem_manager().free(frame, 1)?;
+            return Err(err);
+        }
+    };
+
+    let ptr = virt.mut_ptr::<u64>();
+    let read_back = unsafe {
+        write_volatile(ptr, PATTERN);
+        read_volatile(ptr)
+    };
+    let translated = Translator::new().virt_to_phys(virt).map(|phys| phys.get());
+    Ok(read_back == PATTERN && translated == Some(phys.get()))
 }
@@ -0,0 +1,279 @@
+use super::Device;
+
+// references:
+// PCI Local Bus Specification Revision 3.0, 6.7 Capabilities List
+// https://wiki.osdev.org/PCI#Capabilities_List
+
+/// Offset of the Capabilities Pointer in PCI Configuration Space
+const CAPABILITIES_POINTER_OFFSET: u8 = 0x34;
+/// Bit 4 of the Status register: the device implements the capabilities list
+const STATUS_CAPABILITIES_LIST: u32 = 1 << 4;
+
+/// Capabilities can't be placed in the first 64 bytes (the header) of PCI Configuration Space
+/// so, a capability list has at most (256 - 64) / 4 entries. Used to stop walking a broken (circular) list.
+const MAX_CAPABILITY_COUNT: usize = (256 - 64) / 4;
+
+pub const CAPABILITY_ID_MSI: u8 = 0x05;
+pub const CAPABILITY_ID_MSIX: u8 = 0x11;
+
+const CAPABILITY_RAW_SIZE: usize = 60;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PciCapability {
+    pub id: u8,
+    /// Offset of this capability in PCI Configuration Space
+    pub offset: u8,
+    /// The bytes of PCI Configuration Space from `offset`. Bytes beyond the configuration space are 0.
+    pub raw: [u8; CAPABILITY_RAW_SIZE],
+}
+
+impl PciCapability {
+    /// Byte 1: Next Capability Pointer (0 = the end of the list)
+    pub fn next_pointer(&self) -> u8 {
+        self.raw[1] & 0xfc
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.id {
+            0x01 => "Power Management",
+            0x05 => "MSI",
+            0x09 => "Vendor Specific",
+            0x10 => "PCI Express",
+            0x11 => "MSI-X",
+            0x12 => "SATA",
+            _ => "Unknown",
+        }
+    }
+
+    pub fn as_msi(&self) -> Option<MsiCapability> {
+        if self.id != CAPABILITY_ID_MSI {
+            return None;
+        }
+
+        let read_u16 = |i: usize| u16::from_le_bytes([self.raw[i], self.raw[i + 1]]);
+        let read_u32 = |i: usize| {
+            u32::from_le_bytes([self.raw[i], self.raw[i + 1], self.raw[i + 2], self.raw[i + 3]])
+        };
+
+        let message_control = MsiMessageControl(read_u16(2));
+        let (message_address, message_data) = if message_control.is_64bit_address_capable() {
+            let address = (read_u32(8) as u64) << 32 | read_u32(4) as u64;
+            (address, read_u16(12))
+        } else {
+            (read_u32(4) as u64, read_u16(8))
+        };
+
+        Some(MsiCapability {
+            offset: self.offset,
+            message_control,
+            message_address,
+            message_data,
+        })
+    }
+
+    pub fn as_msix(&self) -> Option<MsixCapability> {
+        if self.id != CAPABILITY_ID_MSIX {
+            return None;
+        }
+
+        let read_u32 = |i: usize| {
+            u32::from_le_bytes([self.raw[i], self.raw[i + 1], self.raw[i + 2], self.raw[i + 3]])
+        };
+
+        // Byte 4-7: Table Offset / Table BIR, Byte 8-11: PBA Offset / PBA BIR
+        // Bit 0-2 is the index of the BAR, the rest is the offset from the BAR (8-byte aligned).
+        let table = read_u32(4);
+        let pba = read_u32(8);
+        Some(MsixCapability {
+            offset: self.offset,
+            message_control: MsixMessageControl(u16::from_le_bytes([self.raw[2], self.raw[3]])),
+            table_bar: (table & 0b111) as u8,
+            table_offset: table & !0b111,
+            pba_bar: (pba & 0b111) as u8,
+            pba_offset: pba & !0b111,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MsiCapability {
+    /// Offset of this capability in PCI Configuration Space
+    pub offset: u8,
+    pub message_control: MsiMessageControl,
+    pub message_address: u64,
+    pub message_data: u16,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MsiMessageControl(u16);
+
+impl MsiMessageControl {
+    pub fn get(&self) -> u16 {
+        self.0
+    }
+
+    /// Bit 0: MSI Enable
+    pub fn is_enabled(&self) -> bool {
+        self.0 & 0b1 != 0
+    }
+
+    /// Bit 1-3: Multiple Message Capable (log2 of the number of requestable vectors)
+    pub fn multiple_message_capable(&self) -> u8 {
+        ((self.0 >> 1) & 0b111) as u8
+    }
+
+    /// Bit 4-6: Multiple Message Enable (log2 of the number of allocated vectors)
+    pub fn multiple_message_enable(&self) -> u8 {
+        ((self.0 >> 4) & 0b111) as u8
+    }
+
+    /// Bit 7: 64 bit Address Capable
+    pub fn is_64bit_address_capable(&self) -> bool {
+        self.0 & (1 << 7) != 0
+    }
+
+    /// Bit 8: Per-vector Masking Capable
+    pub fn is_per_vector_masking_capable(&self) -> bool {
+        self.0 & (1 << 8) != 0
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MsixCapability {
+    /// Offset of this capability in PCI Configuration Space
+    pub offset: u8,
+    pub message_control: MsixMessageControl,
+    /// Index of the BAR which maps the MSI-X Table
+    pub table_bar: u8,
+    /// Offset of the MSI-X Table from the BAR
+    pub table_offset: u32,
+    /// Index of the BAR which maps the Pending Bit Array
+    pub pba_bar: u8,
+    /// Offset of the Pending Bit Array from the BAR
+    pub pba_offset: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MsixMessageControl(u16);
+
+impl MsixMessageControl {
+    pub fn get(&self) -> u16 {
+        self.0
+    }
+
+    /// Bit 0-10: Table Size (the number of entries - 1)
+    pub fn table_size(&self) -> usize {
+        (self.0 & 0x7ff) as usize + 1
+    }
+
+    /// Bit 14: Function Mask (all vectors are masked if set)
+    pub fn is_function_masked(&self) -> bool {
+        self.0 & (1 << 14) != 0
+    }
+
+    /// Bit 15: MSI-X Enable
+    pub fn is_enabled(&self) -> bool {
+        self.0 & (1 << 15) != 0
+    }
+}
+
+/// The size of an entry of the MSI-X Table.
+/// Byte 0-3: Message Address, Byte 4-7: Message Upper Address, Byte 8-11: Message Data,
+/// Byte 12-15: Vector Control (bit 0: Mask)
+pub const MSIX_TABLE_ENTRY_SIZE: usize = 16;
+
+/// Base of the Message Address for MSI. Messages written here are delivered to a local APIC.
+pub const MSI_MESSAGE_ADDRESS_BASE: u32 = 0xfee0_0000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MsiTriggerMode {
+    Edge,
+    Level,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum MsiDeliveryMode {
+    Fixed = 0b000,
+    LowestPriority = 0b001,
+    Smi = 0b010,
+    Nmi = 0b100,
+    Init = 0b101,
+    ExtInt = 0b111,
+}
+
+/// Makes the Message Address which delivers an interrupt to the local APIC of `apic_id`.
+pub fn msi_message_address(apic_id: u8) -> u32 {
+    MSI_MESSAGE_ADDRESS_BASE | (apic_id as u32) << 12
+}
+
+/// Makes the Message Data.
+/// Bit 0-7: Vector
+/// Bit 8-10: Delivery Mode
+/// Bit 14: Level (1 = assert, only meaningful for level triggered interrupts)
+/// Bit 15: Trigger Mode (1 = level)
+pub fn msi_message_data(
+    vector: u8,
+    trigger_mode: MsiTriggerMode,
+    delivery_mode: MsiDeliveryMode,
+) -> u16 {
+    let trigger = match trigger_mode {
+        MsiTriggerMode::Edge => 0,
+        MsiTriggerMode::Level => 1 << 15 | 1 << 14,
+    };
+    vector as u16 | (delivery_mode as u16) << 8 | trigger
+}
+
+/// Iterator over the capability list of a device.
+pub struct Capabilities<'a> {
+    device: &'a Device,
+    next_offset: u8,
+    count: usize,
+}
+
+impl<'a> Capabilities<'a> {
+    pub(super) fn new(device: &'a Device) -> Self {
+        // Status register is the upper 16 bit of offset 0x04.
+        let status = device.read_pci_config_space(0x04) >> 16;
+        let next_offset = if status & STATUS_CAPABILITIES_LIST != 0 {
+            (device.read_pci_config_space(CAPABILITIES_POINTER_OFFSET) & 0xfc) as u8
+        } else {
+            0
+        };
+
+        Self {
+            device,
+            next_offset,
+            count: 0,
+        }
+    }
+}
+
+impl Iterator for Capabilities<'_> {
+    type Item = PciCapability;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_offset == 0 || self.count >= MAX_CAPABILITY_COUNT {
+            return None;
+        }
+
+        let offset = self.next_offset;
+        let mut raw = [0; CAPABILITY_RAW_SIZE];
+        for (i, chunk) in raw.chunks_mut(4).enumerate() {
+            let Some(register_offset) = offset.checked_add(4 * i as u8) else {
+                break;
+            };
+            chunk.copy_from_slice(&self.device.read_pci_config_space(register_offset).to_le_bytes());
+        }
+
+        let capability = PciCapability {
+            id: raw[0],
+            offset,
+            raw,
+        };
+        self.next_offset = capability.next_pointer();
+        self.count += 1;
+
+        Some(capability)
+    }
+}
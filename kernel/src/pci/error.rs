@@ -8,4 +8,6 @@ pub enum PciError {
     UninitializedError,
     DeviceLockError,
     BaseAddressRegisterIndexOutOfRangeError,
+    MsiCapabilityNotFoundError,
+    MsixTableMappingError,
 }
@@ -1,19 +1,34 @@
+pub mod capability;
 pub mod error;
+pub mod names;
 
 use core::{arch::asm, marker::PhantomData};
 
 use arrayvec::ArrayVec;
+use capability::{
+    Capabilities, MsiDeliveryMode, MsiTriggerMode, MsixCapability, PciCapability,
+    CAPABILITY_ID_MSI, CAPABILITY_ID_MSIX, MSIX_TABLE_ENTRY_SIZE,
+};
+use common::address::PhysPtr;
 // use common::arrayvec::ArrayVec;
 use error::PciError;
 use spin::{Mutex, MutexGuard};
 
-use crate::error::Result;
+use crate::{
+    error::Result,
+    kprintln,
+    paging::{self, MapKind},
+};
 
 /// Address of CONFIG_ADDRESS register in IO Address Space
 const CONFIG_ADDRESS_ADDRESS: u16 = 0x0cf8;
 /// Address of CONFIG_DATA register in IO Address Space
 const CONFIG_DATA_ADDRESS: u16 = 0x0cfc;
 
+/// Offset of Command register in PCI Configuration Space
+const COMMAND_OFFSET: u8 = 0x04;
+const COMMAND_MEMORY_SPACE: u16 = 1 << 1;
+
 const DEVICE_CAPACITY: usize = 32;
 static DEVICES: Mutex<Devices<DEVICE_CAPACITY>> = Mutex::new(Devices::new());
 
@@ -41,6 +56,10 @@ impl Device {
         read_vendor_id(self.bus, self.device, self.func)
     }
 
+    pub fn device_id(&self) -> u16 {
+        read_device_id(self.bus, self.device, self.func)
+    }
+
     pub fn is_xhc(&self) -> bool {
         self.class_code.is_match_all(0x0c, 0x03, 0x30)
     }
@@ -69,14 +88,188 @@ impl Device {
         self.header_type
     }
 
-    fn read_pci_config_space(&self, offset_in_pci_config_space: u8) -> u32 {
-        write_address(make_address(
+    /// Returns an iterator over the capability list of this device.
+    pub fn capabilities(&self) -> Capabilities<'_> {
+        Capabilities::new(self)
+    }
+
+    pub fn msi_capability(&self) -> Option<PciCapability> {
+        self.capabilities()
+            .find(|capability| capability.id == CAPABILITY_ID_MSI)
+    }
+
+    pub fn msix_capability(&self) -> Option<PciCapability> {
+        self.capabilities()
+            .find(|capability| capability.id == CAPABILITY_ID_MSIX)
+    }
+
+    pub fn is_msi_capable(&self) -> bool {
+        self.msi_capability().is_some()
+    }
+
+    pub fn is_msix_capable(&self) -> bool {
+        self.msix_capability().is_some()
+    }
+
+    /// Configures MSI to deliver `vector` to the local APIC of `apic_id` and enables it.
+    /// Only a single message is enabled even if the device can request multiple messages.
+    /// Falls back to MSI-X if the device doesn't have the MSI capability.
+    pub fn configure_msi(
+        &self,
+        apic_id: u8,
+        vector: u8,
+        trigger_mode: MsiTriggerMode,
+        delivery_mode: MsiDeliveryMode,
+    ) -> Result<()> {
+        let Some(msi) = self
+            .msi_capability()
+            .and_then(|capability| capability.as_msi())
+        else {
+            let msix = self
+                .msix_capability()
+                .and_then(|capability| capability.as_msix())
+                .ok_or(PciError::MsiCapabilityNotFoundError)?;
+            return self.configure_msix(&msix, apic_id, vector, trigger_mode, delivery_mode);
+        };
+        let offset = msi.offset;
+
+        // Message Address is 4-byte aligned so, the lowest 2 bits are reserved.
+        self.write_pci_config_space(offset + 4, capability::msi_message_address(apic_id));
+
+        // Message Data is 16 bit.
+        let data_offset = if msi.message_control.is_64bit_address_capable() {
+            self.write_pci_config_space(offset + 8, 0);
+            offset + 12
+        } else {
+            offset + 8
+        };
+        let data = capability::msi_message_data(vector, trigger_mode, delivery_mode);
+        self.write_pci_config_space_u16(data_offset, data);
+
+        // Set MSI Enable and clear Multiple Message Enable (= 1 message).
+        let message_control = self.read_pci_config_space_u16(offset + 2);
+        self.write_pci_config_space_u16(offset + 2, (message_control & !(0b111 << 4)) | 0b1);
+
+        Ok(())
+    }
+
+    /// Configures the first entry of the MSI-X Table to deliver `vector` to the local APIC of `apic_id`
+    /// and enables MSI-X. The other entries keep their Mask bit, which is set after reset.
+    fn configure_msix(
+        &self,
+        msix: &MsixCapability,
+        apic_id: u8,
+        vector: u8,
+        trigger_mode: MsiTriggerMode,
+        delivery_mode: MsiDeliveryMode,
+    ) -> Result<()> {
+        const ENABLE: u16 = 1 << 15;
+        const FUNCTION_MASK: u16 = 1 << 14;
+
+        // the flag bits of a memory BAR are bit 0-3
+        let bar = self.read_base_addr(msix.table_bar as usize)? & !0xf;
+        let mut table_phys = PhysPtr::null();
+        table_phys.set(bar + msix.table_offset as u64);
+        let table = paging::map_physical(
+            table_phys,
+            msix.message_control.table_size() * MSIX_TABLE_ENTRY_SIZE,
+            MapKind::Mmio,
+        )
+        .map_err(|_| PciError::MsixTableMappingError)?
+        .mut_ptr::<u32>();
+
+        self.enable_memory_space();
+
+        // Mask all vectors while the table is written.
+        let control_offset = msix.offset + 2;
+        let message_control = self.read_pci_config_space_u16(control_offset);
+        self.write_pci_config_space_u16(control_offset, message_control | ENABLE | FUNCTION_MASK);
+
+        let data = capability::msi_message_data(vector, trigger_mode, delivery_mode);
+        unsafe {
+            table.write_volatile(capability::msi_message_address(apic_id));
+            table.add(1).write_volatile(0);
+            table.add(2).write_volatile(data as u32);
+            // Vector Control: unmask
+            table.add(3).write_volatile(0);
+        }
+
+        self.write_pci_config_space_u16(
+            control_offset,
+            (message_control | ENABLE) & !FUNCTION_MASK,
+        );
+        Ok(())
+    }
+
+    /// Reads the 32 bit register which contains `offset_in_pci_config_space`.
+    pub fn read_pci_config_space(&self, offset_in_pci_config_space: u8) -> u32 {
+        read_config(self.bus, self.device, self.func, offset_in_pci_config_space)
+    }
+
+    /// Writes the 32 bit register which contains `offset_in_pci_config_space`.
+    pub fn write_pci_config_space(&self, offset_in_pci_config_space: u8, value: u32) {
+        write_config(
             self.bus,
             self.device,
             self.func,
             offset_in_pci_config_space,
-        ));
-        read_data()
+            value,
+        );
+    }
+
+    pub fn read_pci_config_space_u8(&self, offset_in_pci_config_space: u8) -> u8 {
+        let shift = (offset_in_pci_config_space & 0b11) * 8;
+        (self.read_pci_config_space(offset_in_pci_config_space) >> shift) as u8
+    }
+
+    /// `offset_in_pci_config_space` must be 2-byte aligned.
+    pub fn read_pci_config_space_u16(&self, offset_in_pci_config_space: u8) -> u16 {
+        debug_assert!(offset_in_pci_config_space % 2 == 0);
+        let shift = (offset_in_pci_config_space & 0b10) * 8;
+        (self.read_pci_config_space(offset_in_pci_config_space) >> shift) as u16
+    }
+
+    /// Writes a byte by read-modify-write of the containing 32 bit register.
+    pub fn write_pci_config_space_u8(&self, offset_in_pci_config_space: u8, value: u8) {
+        let shift = (offset_in_pci_config_space & 0b11) * 8;
+        self.modify_pci_config_space(offset_in_pci_config_space, 0xff << shift, (value as u32) << shift);
+    }
+
+    /// Writes 16 bits by read-modify-write of the containing 32 bit register.
+    /// `offset_in_pci_config_space` must be 2-byte aligned.
+    pub fn write_pci_config_space_u16(&self, offset_in_pci_config_space: u8, value: u16) {
+        debug_assert!(offset_in_pci_config_space % 2 == 0);
+        let shift = (offset_in_pci_config_space & 0b10) * 8;
+        self.modify_pci_config_space(
+            offset_in_pci_config_space,
+            0xffff << shift,
+            (value as u32) << shift,
+        );
+    }
+
+    /// Replaces the bits of `mask` in the 32 bit register with `value` while holding the lock
+    /// so that the read and the write are not interleaved with other accesses.
+    fn modify_pci_config_space(&self, offset_in_pci_config_space: u8, mask: u32, value: u32) {
+        let _lock = CONFIG_SPACE_LOCK.lock();
+        let address = make_address(self.bus, self.device, self.func, offset_in_pci_config_space);
+        write_address(address);
+        let data = read_data();
+        write_address(address);
+        write_data((data & !mask) | (value & mask));
+    }
+
+    /// Command register (offset 0x04, 16 bit)
+    pub fn read_command(&self) -> u16 {
+        self.read_pci_config_space_u16(COMMAND_OFFSET)
+    }
+
+    pub fn write_command(&self, command: u16) {
+        self.write_pci_config_space_u16(COMMAND_OFFSET, command);
+    }
+
+    /// Command register bit 1: Memory Space Enable
+    pub fn enable_memory_space(&self) {
+        self.write_command(self.read_command() | COMMAND_MEMORY_SPACE);
     }
 
     pub fn read_base_addr(&self, index: usize) -> Result<u64> {
@@ -152,6 +345,11 @@ impl ClassCode {
     pub fn get_interface(&self) -> u8 {
         self.interface
     }
+
+    /// Human readable name of the class like "USB controller". Returns "Unknown" for unlisted classes.
+    pub fn name(&self) -> &'static str {
+        names::lookup_class(self.base, self.sub)
+    }
 }
 
 pub struct Devices<'a, const CAP: usize> {
@@ -242,6 +440,77 @@ impl<'a, const CAP: usize> Devices<'a, CAP> {
     }
 }
 
+/// Prints detected devices with their names like lspci. If `verbose` is true, capabilities of each device are also printed.
+pub fn print_devices(verbose: bool) -> Result<()> {
+    for device in devices()?.array.iter() {
+        let class_code = device.get_class_code();
+        let vendor_id = device.vendor_id();
+        let device_id = device.device_id();
+        kprintln!(
+            "{:02x}:{:02x}.{} {} [{:02x}{:02x}{:02x}]: {} {} [{:04x}:{:04x}]{}",
+            device.get_bus(),
+            device.get_device(),
+            device.get_func(),
+            class_code.name(),
+            class_code.get_base(),
+            class_code.get_sub(),
+            class_code.get_interface(),
+            names::lookup_vendor(vendor_id),
+            names::lookup_device(vendor_id, device_id),
+            vendor_id,
+            device_id,
+            if device.is_xhc() { " (xHC)" } else { "" }
+        );
+        kprintln!(
+            "    MSI: {}, MSI-X: {}",
+            if device.is_msi_capable() { "yes" } else { "no" },
+            if device.is_msix_capable() { "yes" } else { "no" }
+        );
+
+        if !verbose {
+            continue;
+        }
+        for capability in device.capabilities() {
+            kprintln!(
+                "    [{:02x}] {} (id 0x{:02x})",
+                capability.offset,
+                capability.name(),
+                capability.id
+            );
+            if let Some(msi) = capability.as_msi() {
+                kprintln!(
+                    "        enabled: {}, 64 bit: {}, address: 0x{:x}, data: 0x{:x}",
+                    msi.message_control.is_enabled(),
+                    msi.message_control.is_64bit_address_capable(),
+                    msi.message_address,
+                    msi.message_data
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Finds the xHC (an Intel one if there are several) and configures its MSI, or MSI-X, to deliver
+/// level triggered `vector` interrupts to the local APIC of `apic_id`.
+/// Returns the xHC, or None if there is no xHC. The controller raises interrupts once a driver starts it.
+pub fn configure_xhc_msi(apic_id: u8, vector: u8) -> Result<Option<Device>> {
+    let xhc = {
+        let devices = devices()?;
+        let mut xhcs = devices.array.iter().filter(|device| device.is_xhc());
+        match xhcs.clone().find(|device| device.is_intel()) {
+            Some(xhc) => Some(*xhc),
+            None => xhcs.next().copied(),
+        }
+    };
+    let Some(xhc) = xhc else {
+        return Ok(None);
+    };
+
+    xhc.configure_msi(apic_id, vector, MsiTriggerMode::Level, MsiDeliveryMode::Fixed)?;
+    Ok(Some(xhc))
+}
+
 pub fn devices() -> Result<MutexGuard<'static, Devices<'static, DEVICE_CAPACITY>>> {
     DEVICES.try_lock().ok_or(PciError::DeviceLockError.into())
 }
@@ -261,6 +530,24 @@ fn make_address(bus: u8, device: u8, func: u8, offset_in_pci_config_space: u8) -
         | (offset_in_pci_config_space as u32 & 0xfc) // Register offset (8bit) (2bit aligned)
 }
 
+/// Held while CONFIG_ADDRESS and CONFIG_DATA are accessed as a pair so that
+/// another access can't change CONFIG_ADDRESS in between.
+static CONFIG_SPACE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Reads a 32 bit register of PCI Configuration Space.
+fn read_config(bus: u8, device: u8, func: u8, offset_in_pci_config_space: u8) -> u32 {
+    let _lock = CONFIG_SPACE_LOCK.lock();
+    write_address(make_address(bus, device, func, offset_in_pci_config_space));
+    read_data()
+}
+
+/// Writes a 32 bit register of PCI Configuration Space.
+fn write_config(bus: u8, device: u8, func: u8, offset_in_pci_config_space: u8, value: u32) {
+    let _lock = CONFIG_SPACE_LOCK.lock();
+    write_address(make_address(bus, device, func, offset_in_pci_config_space));
+    write_data(value);
+}
+
 /// writes an address of PCI Configuration Space to CONFIG_ADDRESS register to read/write it via CONFIG_DATA register.
 fn write_address(addr: u32) {
     io_out_32(CONFIG_ADDRESS_ADDRESS, addr);
@@ -279,26 +566,22 @@ fn read_data() -> u32 {
 // functions to read informations from PCI Configuration Space
 /// Length of Vendor ID is 16 bit.
 fn read_vendor_id(bus: u8, device: u8, func: u8) -> u16 {
-    write_address(make_address(bus, device, func, 0x00));
-    (read_data() & 0xffff) as u16
+    (read_config(bus, device, func, 0x00) & 0xffff) as u16
 }
 
 /// Length of Device ID is 16 bit.
 fn read_device_id(bus: u8, device: u8, func: u8) -> u16 {
-    write_address(make_address(bus, device, func, 0x00));
-    (read_data() >> 16) as u16
+    (read_config(bus, device, func, 0x00) >> 16) as u16
 }
 
 /// Length of Header Type is 8 bit.
 fn read_header_type(bus: u8, device: u8, func: u8) -> u8 {
-    write_address(make_address(bus, device, func, 0x0c));
-    ((read_data() >> 16) & 0xff) as u8
+    ((read_config(bus, device, func, 0x0c) >> 16) & 0xff) as u8
 }
 
 /// Length of Class Code is 24 bit.
 fn read_class_code(bus: u8, device: u8, func: u8) -> ClassCode {
-    write_address(make_address(bus, device, func, 0x08));
-    let class_code_raw = read_data() >> 8;
+    let class_code_raw = read_config(bus, device, func, 0x08) >> 8;
     let base = ((class_code_raw >> 16) & 0xff) as u8;
     let sub = ((class_code_raw >> 8) & 0xff) as u8;
     let interface = (class_code_raw & 0xff) as u8;
@@ -307,8 +590,7 @@ fn read_class_code(bus: u8, device: u8, func: u8) -> ClassCode {
 }
 
 fn read_bus_numbers(bus: u8, device: u8, func: u8) -> u32 {
-    write_address(make_address(bus, device, func, 0x18));
-    read_data()
+    read_config(bus, device, func, 0x18)
 }
 
 fn io_out_32(addr: u16, data: u32) {
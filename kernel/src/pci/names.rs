@@ -0,0 +1,107 @@
+// Human readable names of PCI vendors, devices and classes.
+// Only common ones (mainly devices emulated by QEMU) are listed.
+// references:
+// https://pci-ids.ucw.cz/
+
+const VENDORS: &[(u16, &str)] = &[
+    (0x1002, "AMD/ATI"),
+    (0x1022, "AMD"),
+    (0x10de, "NVIDIA"),
+    (0x10ec, "Realtek"),
+    (0x1234, "QEMU"),
+    (0x1af4, "Virtio"),
+    (0x1b36, "Red Hat"),
+    (0x15ad, "VMware"),
+    (0x1033, "NEC"),
+    (0x8086, "Intel"),
+];
+
+const DEVICES: &[(u16, u16, &str)] = &[
+    (0x1234, 0x1111, "Standard VGA"),
+    (0x1af4, 0x1000, "Virtio network device"),
+    (0x1af4, 0x1001, "Virtio block device"),
+    (0x1af4, 0x1041, "Virtio 1.0 network device"),
+    (0x1af4, 0x1042, "Virtio 1.0 block device"),
+    (0x1af4, 0x1050, "Virtio 1.0 GPU"),
+    (0x1b36, 0x0001, "QEMU PCI-PCI bridge"),
+    (0x1b36, 0x000d, "QEMU XHCI Host Controller"),
+    (0x1033, 0x0194, "uPD720200 USB 3.0 Host Controller"),
+    (0x8086, 0x100e, "82540EM Gigabit Ethernet Controller"),
+    (0x8086, 0x10d3, "82574L Gigabit Network Connection"),
+    (0x8086, 0x1237, "440FX - 82441FX PMC [Natoma]"),
+    (0x8086, 0x2918, "82801IB (ICH9) LPC Interface Controller"),
+    (0x8086, 0x2922, "82801IR/IO/IH (ICH9R/DO/DH) 6 port SATA Controller [AHCI mode]"),
+    (0x8086, 0x2930, "82801I (ICH9 Family) SMBus Controller"),
+    (0x8086, 0x29c0, "82G33/G31/P35/P31 Express DRAM Controller"),
+    (0x8086, 0x7000, "82371SB PIIX3 ISA [Natoma/Triton II]"),
+    (0x8086, 0x7010, "82371SB PIIX3 IDE [Natoma/Triton II]"),
+    (0x8086, 0x7020, "82371SB PIIX3 USB [Natoma/Triton II]"),
+    (0x8086, 0x7113, "82371AB/EB/MB PIIX4 ACPI"),
+];
+
+const BASE_CLASSES: &[(u8, &str)] = &[
+    (0x00, "Unclassified device"),
+    (0x01, "Mass storage controller"),
+    (0x02, "Network controller"),
+    (0x03, "Display controller"),
+    (0x04, "Multimedia controller"),
+    (0x05, "Memory controller"),
+    (0x06, "Bridge"),
+    (0x07, "Communication controller"),
+    (0x08, "Generic system peripheral"),
+    (0x09, "Input device controller"),
+    (0x0a, "Docking station"),
+    (0x0b, "Processor"),
+    (0x0c, "Serial bus controller"),
+    (0x0d, "Wireless controller"),
+    (0x0e, "Intelligent controller"),
+    (0x0f, "Satellite communications controller"),
+    (0x10, "Encryption controller"),
+    (0x11, "Signal processing controller"),
+    (0x12, "Processing accelerators"),
+    (0x13, "Non-Essential Instrumentation"),
+    (0x40, "Coprocessor"),
+    (0xff, "Unassigned class"),
+];
+
+const SUB_CLASSES: &[(u8, u8, &str)] = &[
+    (0x01, 0x01, "IDE interface"),
+    (0x01, 0x06, "SATA controller"),
+    (0x01, 0x08, "Non-Volatile memory controller"),
+    (0x02, 0x00, "Ethernet controller"),
+    (0x03, 0x00, "VGA compatible controller"),
+    (0x06, 0x00, "Host bridge"),
+    (0x06, 0x01, "ISA bridge"),
+    (0x06, 0x04, "PCI bridge"),
+    (0x0c, 0x03, "USB controller"),
+    (0x0c, 0x05, "SMBus"),
+];
+
+pub fn lookup_vendor(vendor_id: u16) -> &'static str {
+    VENDORS
+        .iter()
+        .find(|(id, _)| *id == vendor_id)
+        .map_or("Unknown vendor", |(_, name)| name)
+}
+
+pub fn lookup_device(vendor_id: u16, device_id: u16) -> &'static str {
+    DEVICES
+        .iter()
+        .find(|(vendor, device, _)| *vendor == vendor_id && *device == device_id)
+        .map_or("Unknown device", |(_, _, name)| name)
+}
+
+/// Looks up the name of the sub class first and falls back to the name of the base class.
+pub fn lookup_class(base: u8, sub: u8) -> &'static str {
+    SUB_CLASSES
+        .iter()
+        .find(|(b, s, _)| *b == base && *s == sub)
+        .map(|(_, _, name)| *name)
+        .or_else(|| {
+            BASE_CLASSES
+                .iter()
+                .find(|(class, _)| *class == base)
+                .map(|(_, name)| *name)
+        })
+        .unwrap_or("Unknown")
+}
@@ -1,3 +1,5 @@
+pub use common::scan_code::ScanCodeSet;
+
 use super::controller::{Controller, ControllerError};
 
 type Result<T> = core::result::Result<T, KeyboardError>;
@@ -71,6 +73,7 @@ impl Response {
 
 #[derive(Debug)]
 enum Command {
+    GetSetScanCodeSet = 0xf0,
     ResetAndSelfTest = 0xff,
 }
 
@@ -128,6 +131,18 @@ impl<'a> Keyboard<'a> {
         };
     }
 
+    /// Sends 0xF0 with 0 to get the current scan code set. The keyboard answers with ACK and the set number.
+    /// The first port translation must be disabled, otherwise the number is translated too.
+    pub unsafe fn get_scan_code_set(&mut self) -> Result<ScanCodeSet> {
+        unsafe { self.write_command(Command::GetSetScanCodeSet, Some(0)) }?;
+        let set = unsafe { self.controller.read_data() }?;
+        ScanCodeSet::from_u8(set).ok_or(KeyboardError::InvalidResponse)
+    }
+
+    pub unsafe fn set_scan_code_set(&mut self, set: ScanCodeSet) -> Result<()> {
+        unsafe { self.write_command(Command::GetSetScanCodeSet, Some(set.as_u8())) }
+    }
+
     pub unsafe fn read_data(&mut self) -> Result<u8> {
         return Ok(unsafe { self.controller.read_data()? });
     }
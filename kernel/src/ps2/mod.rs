@@ -1,4 +1,6 @@
 use controller::Controller;
+use keyboard::ScanCodeSet;
+use spin::Once;
 
 use crate::kprintln;
 
@@ -6,10 +8,42 @@ pub mod controller;
 pub mod keyboard;
 pub mod mouse;
 
+static SCAN_CODE_SET: Once<ScanCodeSet> = Once::new();
+
 pub fn controller() -> Controller {
     Controller::new()
 }
 
+/// Returns the scan code set which the keyboard sends.
+pub fn scan_code_set() -> ScanCodeSet {
+    *SCAN_CODE_SET
+        .get()
+        .expect("ps2::scan_code_set is called before calling ps2::init.")
+}
+
+/// Switches the keyboard to scan code set 1. If the keyboard doesn't support it,
+/// keeps set 2, which every PS/2 keyboard supports.
+fn select_scan_code_set(controller: &mut Controller) -> ScanCodeSet {
+    let mut keyboard = controller.keyboard();
+    match unsafe { keyboard.get_scan_code_set() } {
+        Ok(set) => kprintln!("the keyboard uses scan code {:?}.", set),
+        Err(err) => kprintln!("failed to get the scan code set: {:?}", err),
+    }
+
+    let switched = unsafe { keyboard.set_scan_code_set(ScanCodeSet::Set1) }.is_ok()
+        && matches!(unsafe { keyboard.get_scan_code_set() }, Ok(ScanCodeSet::Set1));
+    if switched {
+        return ScanCodeSet::Set1;
+    }
+
+    kprintln!("failed to switch to scan code set 1. falling back to set 2.");
+    // Some keyboards reject the command so, make sure set 2 is active.
+    if let Err(err) = unsafe { keyboard.set_scan_code_set(ScanCodeSet::Set2) } {
+        kprintln!("failed to switch to scan code set 2: {:?}", err);
+    }
+    ScanCodeSet::Set2
+}
+
 pub fn init() {
     // https://wiki.osdev.org/%228042%22_PS/2_Controller#Initialising%20the%20PS/2%20Controller
 
@@ -105,6 +139,10 @@ pub fn init() {
     unsafe { controller.mouse().reset_and_self_test() }
         .unwrap_or_else(|err| panic!("failed to reset the mouse: {:?}", err));
 
+    let scan_code_set = select_scan_code_set(&mut controller);
+    kprintln!("scan code {:?} is active.", scan_code_set);
+    SCAN_CODE_SET.call_once(|| scan_code_set);
+
     // enable mouse's data-reporting
     if second_port_works {
         unsafe { controller.mouse().enable_data_reporting() }.unwrap_or_else(|err| {
@@ -0,0 +1,16 @@
+use x86_64::instructions::port::Port;
+
+// references:
+// hw/misc/debugexit.c in the QEMU source
+
+/// Address of the isa-debug-exit device in IO Address Space. It must match `iobase` in Makefile.toml.
+const ISA_DEBUG_EXIT_PORT: u16 = 0xf4;
+/// QEMU exits with `(value << 1) | 1`, so this makes the exit status 33.
+const EXIT_VALUE: u32 = 0x10;
+
+/// Exits QEMU through the isa-debug-exit device.
+/// Returns if the device doesn't exist, e.g. on real hardware or QEMU without `-device isa-debug-exit`.
+pub fn exit_qemu() {
+    let mut port = Port::<u32>::new(ISA_DEBUG_EXIT_PORT);
+    unsafe { port.write(EXIT_VALUE) };
+}
@@ -0,0 +1,247 @@
+use core::fmt::Write;
+
+use arrayvec::ArrayString;
+use common::bcd::{bcd_to_binary, binary_to_bcd};
+use x86_64::instructions::port::{Port, PortWriteOnly};
+
+// references:
+// https://wiki.osdev.org/CMOS
+
+/// Address of the CMOS register selector in IO Address Space
+const CMOS_ADDRESS_ADDRESS: u16 = 0x70;
+/// Address of the CMOS data register in IO Address Space
+const CMOS_DATA_ADDRESS: u16 = 0x71;
+
+/// Bit 7 of the register selector disables NMIs while the CMOS is accessed.
+/// It stays set until the selector is written again, so every access clears it afterwards.
+const DISABLE_NMI: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Formats the date time as "YYYY-MM-DD HH:MM:SS".
+    pub fn format(&self) -> ArrayString<32> {
+        let mut s = ArrayString::new();
+        write!(
+            s,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+        .expect("A formatted date time never exceeds 32 bytes.");
+        s
+    }
+}
+
+pub struct Rtc {
+    address_port: PortWriteOnly<u8>,
+    data_port: Port<u8>,
+}
+
+impl Rtc {
+    pub fn new() -> Self {
+        Self {
+            address_port: PortWriteOnly::new(CMOS_ADDRESS_ADDRESS),
+            data_port: Port::new(CMOS_DATA_ADDRESS),
+        }
+    }
+
+    unsafe fn read_register(&mut self, register: Register) -> u8 {
+        let index = register.as_u8();
+        unsafe {
+            self.address_port.write(DISABLE_NMI | index);
+            let value = self.data_port.read();
+            self.address_port.write(index);
+            value
+        }
+    }
+
+    unsafe fn write_register(&mut self, register: Register, value: u8) {
+        let index = register.as_u8();
+        unsafe {
+            self.address_port.write(DISABLE_NMI | index);
+            self.data_port.write(value);
+            self.address_port.write(index);
+        }
+    }
+
+    /// Status register A bit 7: Update in progress (1 = the RTC is updating the time registers)
+    fn is_update_in_progress(&mut self) -> bool {
+        unsafe { self.read_register(Register::StatusA) & (1 << 7) != 0 }
+    }
+
+    /// Reads the raw time registers once the RTC has finished any update in progress.
+    fn read_raw(&mut self) -> RawDateTime {
+        while self.is_update_in_progress() {}
+
+        unsafe {
+            RawDateTime {
+                second: self.read_register(Register::Seconds),
+                minute: self.read_register(Register::Minutes),
+                hour: self.read_register(Register::Hours),
+                day: self.read_register(Register::DayOfMonth),
+                month: self.read_register(Register::Month),
+                year: self.read_register(Register::Year),
+            }
+        }
+    }
+
+    pub fn read(&mut self) -> DateTime {
+        // An update can still start between the check of the flag and the reads,
+        // so read until two consecutive reads agree.
+        let mut raw = self.read_raw();
+        loop {
+            let next = self.read_raw();
+            if next == raw {
+                break;
+            }
+            raw = next;
+        }
+
+        let status_b = StatusB(unsafe { self.read_register(Register::StatusB) });
+        raw.decode(status_b)
+    }
+
+    pub fn write(&mut self, date_time: &DateTime) {
+        let status_b = StatusB(unsafe { self.read_register(Register::StatusB) });
+        let raw = RawDateTime::encode(date_time, status_b);
+
+        unsafe {
+            // Stop the RTC from updating the time registers while they are written.
+            self.write_register(Register::StatusB, status_b.get() | StatusB::SET);
+
+            self.write_register(Register::Seconds, raw.second);
+            self.write_register(Register::Minutes, raw.minute);
+            self.write_register(Register::Hours, raw.hour);
+            self.write_register(Register::DayOfMonth, raw.day);
+            self.write_register(Register::Month, raw.month);
+            self.write_register(Register::Year, raw.year);
+
+            self.write_register(Register::StatusB, status_b.get() & !StatusB::SET);
+        }
+    }
+}
+
+pub fn rtc() -> Rtc {
+    Rtc::new()
+}
+
+enum Register {
+    Seconds = 0x00,
+    Minutes = 0x02,
+    Hours = 0x04,
+    DayOfMonth = 0x07,
+    Month = 0x08,
+    Year = 0x09,
+    StatusA = 0x0a,
+    StatusB = 0x0b,
+}
+
+impl Register {
+    fn as_u8(self) -> u8 {
+        self as u8
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct StatusB(u8);
+
+impl StatusB {
+    /// Bit 7: Stop updating the time registers while set
+    const SET: u8 = 1 << 7;
+
+    fn get(self) -> u8 {
+        self.0
+    }
+
+    /// Bit 1: Hour format (1 = 24 hour, 0 = 12 hour)
+    fn is_24_hour(&self) -> bool {
+        self.0 & (1 << 1) != 0
+    }
+
+    /// Bit 2: Data mode (1 = binary, 0 = BCD)
+    fn is_binary(&self) -> bool {
+        self.0 & (1 << 2) != 0
+    }
+}
+
+/// Bit 7 of the hour register is the PM flag in 12 hour format.
+const HOUR_PM: u8 = 0x80;
+
+/// The time registers as they are stored in the CMOS.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct RawDateTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+}
+
+impl RawDateTime {
+    fn decode(self, status_b: StatusB) -> DateTime {
+        let to_binary = |value: u8| {
+            if status_b.is_binary() {
+                value
+            } else {
+                bcd_to_binary(value)
+            }
+        };
+
+        let mut hour = to_binary(self.hour & !HOUR_PM);
+        if !status_b.is_24_hour() {
+            // 12 AM is midnight and 12 PM is noon.
+            hour %= 12;
+            if self.hour & HOUR_PM != 0 {
+                hour += 12;
+            }
+        }
+
+        DateTime {
+            // The year register only holds the last two digits.
+            year: 2000 + to_binary(self.year) as u16,
+            month: to_binary(self.month),
+            day: to_binary(self.day),
+            hour,
+            minute: to_binary(self.minute),
+            second: to_binary(self.second),
+        }
+    }
+
+    fn encode(date_time: &DateTime, status_b: StatusB) -> Self {
+        let from_binary = |value: u8| {
+            if status_b.is_binary() {
+                value
+            } else {
+                binary_to_bcd(value)
+            }
+        };
+
+        let hour = if status_b.is_24_hour() {
+            from_binary(date_time.hour)
+        } else {
+            let pm = if date_time.hour >= 12 { HOUR_PM } else { 0 };
+            match date_time.hour % 12 {
+                0 => from_binary(12) | pm,
+                hour => from_binary(hour) | pm,
+            }
+        };
+
+        Self {
+            second: from_binary(date_time.second),
+            minute: from_binary(date_time.minute),
+            hour,
+            day: from_binary(date_time.day),
+            month: from_binary(date_time.month),
+            year: from_binary((date_time.year % 100) as u8),
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use core::fmt;
+
+use spin::{Mutex, MutexGuard};
+use thiserror_no_std::Error;
+use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
+
+use crate::message::{self, Message};
+
+// references:
+// https://wiki.osdev.org/Serial_Ports
+
+/// Base address of COM1 in IO Address Space
+const COM1_BASE: u16 = 0x3f8;
+
+static COM1: Mutex<SerialPort> = Mutex::new(SerialPort::new(COM1_BASE));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum SerialError {
+    #[error("The loopback test failed. The serial port is faulty or doesn't exist.")]
+    LoopbackTestFailed,
+}
+
+pub struct SerialPort {
+    /// Receive buffer (read), transmit buffer (write) or the low byte of the divisor (DLAB = 1)
+    data: Port<u8>,
+    /// Interrupt enable register or the high byte of the divisor (DLAB = 1)
+    interrupt_enable: PortWriteOnly<u8>,
+    fifo_control: PortWriteOnly<u8>,
+    line_control: PortWriteOnly<u8>,
+    modem_control: PortWriteOnly<u8>,
+    line_status: PortReadOnly<u8>,
+}
+
+impl SerialPort {
+    const fn new(base: u16) -> Self {
+        Self {
+            data: Port::new(base),
+            interrupt_enable: PortWriteOnly::new(base + 1),
+            fifo_control: PortWriteOnly::new(base + 2),
+            line_control: PortWriteOnly::new(base + 3),
+            modem_control: PortWriteOnly::new(base + 4),
+            line_status: PortReadOnly::new(base + 5),
+        }
+    }
+
+    /// Initializes the port as 38400 baud, 8 data bits, no parity and 1 stop bit,
+    /// and enables the interrupt on received data.
+    pub fn init(&mut self) -> Result<(), SerialError> {
+        unsafe {
+            self.interrupt_enable.write(0x00); // disable all interrupts
+            self.line_control.write(0x80); // enable DLAB to set the baud rate divisor
+            self.data.write(0x03); // divisor low byte: 115200 / 3 = 38400 baud
+            self.interrupt_enable.write(0x00); // divisor high byte
+            self.line_control.write(0x03); // 8 bits, no parity, one stop bit, DLAB = 0
+            self.fifo_control.write(0xc7); // enable and clear FIFO, 14-byte threshold
+            self.modem_control.write(0x1e); // loopback mode to test the port
+
+            self.data.write(0xae);
+            if self.data.read() != 0xae {
+                return Err(SerialError::LoopbackTestFailed);
+            }
+
+            // normal operation mode: DTR, RTS and OUT2 (OUT2 connects the IRQ line)
+            self.modem_control.write(0x0f);
+            self.interrupt_enable.write(0x01); // interrupt on received data
+        }
+        Ok(())
+    }
+
+    /// Line status register bit 0: Data ready
+    fn is_data_ready(&mut self) -> bool {
+        unsafe { self.line_status.read() & 0b1 != 0 }
+    }
+
+    /// Line status register bit 5: Transmitter holding register empty
+    fn is_transmit_empty(&mut self) -> bool {
+        unsafe { self.line_status.read() & (1 << 5) != 0 }
+    }
+
+    pub fn write_byte(&mut self, byte: u8) {
+        while !self.is_transmit_empty() {}
+        unsafe { self.data.write(byte) };
+    }
+
+    /// Returns a received byte if there is one, without waiting.
+    pub fn read_byte(&mut self) -> Option<u8> {
+        if self.is_data_ready() {
+            Some(unsafe { self.data.read() })
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+            self.write_byte(byte);
+        }
+        Ok(())
+    }
+}
+
+pub fn serial() -> MutexGuard<'static, SerialPort> {
+    COM1.lock()
+}
+
+/// Returns a handle to COM1 which doesn't take the lock.
+/// Only for the panic handler and the interrupt handler, where the lock may be held by the interrupted code.
+/// The output can interleave with the output of the lock holder.
+pub fn emergency_serial() -> SerialPort {
+    SerialPort::new(COM1_BASE)
+}
+
+pub fn init() -> Result<(), SerialError> {
+    serial().init()
+}
+
+/// Polls COM1 for a received byte.
+pub fn read_byte() -> Option<u8> {
+    serial().read_byte()
+}
+
+/// Called from the interrupt handler of COM1. Posts all received bytes to the main loop.
+pub fn on_interrupt() {
+    // The interrupted code may hold the lock to write. Reading the receive buffer
+    // and the line status doesn't disturb the transmission, so the lock is not taken.
+    let mut serial = emergency_serial();
+    while let Some(byte) = serial.read_byte() {
+        message::enqueue_from_interrupt(Message::SerialInput(byte));
+    }
+}
+
+#[macro_export]
+macro_rules! serial_println {
+    ($($arg:tt)*) => {{
+        use core::fmt::Write;
+        let mut serial = crate::serial::serial();
+        serial.write_fmt(core::format_args!($($arg)*)).unwrap();
+        serial.write_str("\n").unwrap();
+    }};
+}
+
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => {{
+        use core::fmt::Write;
+        crate::serial::serial().write_fmt(core::format_args!($($arg)*)).unwrap();
+    }};
+}
+
+/// `serial_println!` without taking the lock. Only for the panic handler.
+#[macro_export]
+macro_rules! serial_emergency_println {
+    ($($arg:tt)*) => {{
+        use core::fmt::Write;
+        let mut serial = crate::serial::emergency_serial();
+        let _ = serial.write_fmt(core::format_args!($($arg)*));
+        let _ = serial.write_str("\n");
+    }};
+}
@@ -1,16 +1,28 @@
-use core::ptr::write_volatile;
+pub mod precise;
 
-use spin::Mutex;
+use core::sync::atomic::{AtomicU64, Ordering};
 
-use crate::{interrupts, kprintln};
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::{
+    acpi, interrupts,
+    message::{self, Message},
+};
 
 const COUNT_MAX: u32 = 0xffffffff;
+/// Frequency of the periodic local APIC timer interrupt in Hz.
+pub const TIMER_FREQ: u64 = 100;
 // const LVT_TIMER: *mut u32 = 0xfee00320 as *mut u32;
 // const INITIAL_COUNT: *mut u32 = 0xfee00380 as *mut u32;
 // const CURRENT_COUNT: *mut u32 = 0xfee00390 as *mut u32;
 // const DIVIDE_CONFIG: *mut u32 = 0xfee003e0 as *mut u32;
 
 static TIMER_MANAGER: Mutex<TimerManager> = Mutex::new(TimerManager::new());
+/// A copy of `TimerManager::tick` which can be read without taking TIMER_MANAGER.
+static TICKS: AtomicU64 = AtomicU64::new(0);
+/// Frequency of the local APIC timer count measured with the ACPI PM timer.
+static LOCAL_APIC_TIMER_FREQ: Once<u64> = Once::new();
 
 struct TimerManager {
     tick: u64,
@@ -23,6 +35,7 @@ impl TimerManager {
 
     fn tick(&mut self) {
         self.tick += 1;
+        TICKS.store(self.tick, Ordering::Relaxed);
     }
 
     fn current_tick(&self) -> u64 {
@@ -31,28 +44,51 @@ impl TimerManager {
 }
 
 pub fn local_apic_timer_on_interrupt() {
-    TIMER_MANAGER.lock().tick();
+    let mut timer_manager = TIMER_MANAGER.lock();
+    timer_manager.tick();
+    if timer_manager.current_tick() % TIMER_FREQ == 0 {
+        message::enqueue_from_interrupt(Message::TimerSecond);
+    }
 }
 
 pub fn current_tick() -> u64 {
-    TIMER_MANAGER.lock().current_tick()
+    // The timer interrupt handler takes the same lock, so keep interrupts off while holding it.
+    without_interrupts(|| TIMER_MANAGER.lock().current_tick())
+}
+
+/// Ticks elapsed since the local APIC timer was started. Unlike `current_tick`, doesn't take the lock
+/// so, this can be called anywhere including interrupt handlers.
+pub fn uptime_ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Milliseconds elapsed since the local APIC timer was started.
+pub fn uptime_ms() -> u64 {
+    uptime_ticks() * 1000 / TIMER_FREQ
 }
 
 /// - divide: 1:1
 /// - not-masked
-/// - mode: periodic
+/// - mode: periodic (TIMER_FREQ Hz)
 pub fn init_local_apic_timer() {
-    // write_volatile(DIVIDE_CONFIG, 0b1011); // divide 1:1
-    interrupts::get_local_apic().write_divide_config_register_for_timer(0b1011);
-    // write_volatile(
-    //     LVT_TIMER,
-    //     (0b010 << 16) | interrupts::InterruptVector::LocalAPICTimer as u32,
-    // ); // not-masked, periodic
-    interrupts::get_local_apic()
-        .set_lvt_timer_register((0b010 << 16) | interrupts::InterruptVector::LocalAPICTimer as u32);
-    // not-masked, periodic
-    // write_volatile(INITIAL_COUNT, 0x1000000);
-    interrupts::get_local_apic().write_initial_count_register_for_timer(0x1000000);
+    const MASKED: u32 = 1 << 16;
+    let local_apic = interrupts::get_local_apic();
+
+    // Measure how many counts the local APIC timer advances in 100 milliseconds.
+    local_apic.write_divide_config_register_for_timer(0b1011); // divide 1:1
+    local_apic.write_lvt_timer_register(MASKED); // masked, one-shot
+    start_local_apic_timer();
+    acpi::wait_milli_secs(100);
+    let elapsed = local_apic_timer_elapsed();
+    stop_local_apic_timer();
+
+    let local_apic_timer_freq = *LOCAL_APIC_TIMER_FREQ.call_once(|| elapsed as u64 * 10);
+
+    local_apic.write_divide_config_register_for_timer(0b1011); // divide 1:1
+    local_apic.write_lvt_timer_register(
+        (0b010 << 16) | interrupts::InterruptVector::LocalAPICTimer as u32,
+    ); // not-masked, periodic
+    local_apic.write_initial_count_register_for_timer((local_apic_timer_freq / TIMER_FREQ) as u32);
 }
 
 pub fn start_local_apic_timer() {
@@ -64,7 +100,7 @@ pub fn start_local_apic_timer() {
 
 pub fn local_apic_timer_elapsed() -> u32 {
     // return unsafe { COUNT_MAX - *CURRENT_COUNT };
-    return COUNT_MAX - interrupts::get_local_apic().get_current_count_register_for_timer();
+    return COUNT_MAX - interrupts::get_local_apic().read_current_count_register_for_timer();
 }
 
 pub fn stop_local_apic_timer() {
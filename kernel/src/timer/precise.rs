@@ -0,0 +1,101 @@
+use core::arch::x86_64::_rdtsc;
+
+use spin::Once;
+
+use crate::{acpi, cpu, kprintln};
+
+/// The clock which `Stopwatch` counts with.
+#[derive(Debug, Clone, Copy)]
+enum Clock {
+    /// Frequency of the TSC in Hz
+    Tsc(u64),
+    /// The ACPI PM timer. Used when the TSC rate may change with power states.
+    PmTimer,
+}
+
+static CLOCK: Once<Clock> = Once::new();
+
+const CALIBRATION_MILLI_SECS: u64 = 50;
+
+/// Measures the TSC frequency against the ACPI PM timer.
+/// Falls back to the PM timer if CPUID doesn't advertise an invariant TSC.
+pub fn init() {
+    let clock = if cpu::has_tsc() && cpu::has_invariant_tsc() {
+        let start = read_tsc();
+        acpi::wait_milli_secs(CALIBRATION_MILLI_SECS);
+        let end = read_tsc();
+
+        let tsc_freq = (end - start) * 1000 / CALIBRATION_MILLI_SECS;
+        kprintln!("TSC frequency: {} MHz", tsc_freq / 1_000_000);
+        Clock::Tsc(tsc_freq)
+    } else {
+        kprintln!("Invariant TSC isn't available. The ACPI PM timer is used instead.");
+        Clock::PmTimer
+    };
+    CLOCK.call_once(|| clock);
+}
+
+fn clock() -> Clock {
+    *CLOCK
+        .get()
+        .expect("timer::precise::clock is called before calling timer::precise::init.")
+}
+
+fn read_tsc() -> u64 {
+    _rdtsc()
+}
+
+/// Converts `count` of a counter advancing at `freq` Hz to nanoseconds without overflowing.
+fn count_to_ns(count: u64, freq: u64) -> u64 {
+    (count as u128 * 1_000_000_000 / freq as u128) as u64
+}
+
+/// Nanoseconds since the CPU was reset, based on the TSC.
+/// Returns None before `init` or if the TSC isn't used because it isn't invariant.
+pub fn rdtsc_ns() -> Option<u64> {
+    match *CLOCK.get()? {
+        Clock::Tsc(tsc_freq) => Some(count_to_ns(read_tsc(), tsc_freq)),
+        Clock::PmTimer => None,
+    }
+}
+
+/// Measures elapsed time in nanoseconds.
+/// With the PM timer fallback, intervals longer than the wrap-around period of the counter
+/// (about 4.7 seconds for a 24 bit counter) can't be measured correctly.
+#[derive(Debug, Clone, Copy)]
+pub struct Stopwatch {
+    start: u64,
+}
+
+impl Stopwatch {
+    pub fn start() -> Self {
+        Self { start: read_counter() }
+    }
+
+    /// `start` which returns None instead of panicking before `init`.
+    pub fn try_start() -> Option<Self> {
+        CLOCK.get().is_some().then(Self::start)
+    }
+
+    pub fn elapsed_ns(&self) -> u64 {
+        let now = read_counter();
+        match clock() {
+            Clock::Tsc(tsc_freq) => count_to_ns(now - self.start, tsc_freq),
+            Clock::PmTimer => {
+                let count = (now as u32).wrapping_sub(self.start as u32) & acpi::pm_timer_mask();
+                count_to_ns(count as u64, acpi::PM_TIMER_FREQ)
+            }
+        }
+    }
+
+    pub fn elapsed_us(&self) -> u64 {
+        self.elapsed_ns() / 1000
+    }
+}
+
+fn read_counter() -> u64 {
+    match clock() {
+        Clock::Tsc(_) => read_tsc(),
+        Clock::PmTimer => acpi::read_pm_timer() as u64,
+    }
+}